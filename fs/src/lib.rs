@@ -0,0 +1,6 @@
+#![no_std]
+
+extern crate alloc;
+
+pub mod block_dev;
+pub mod block_cache;
@@ -1,6 +1,7 @@
 #![no_std]
 #![no_main]
 #![feature(panic_info_message)]
+#![feature(alloc_error_handler)]
 
 extern crate alloc;
 #[macro_use]
@@ -13,7 +14,7 @@ use riscv::register::{mepc, mhartid, mie, mstatus, mtvec, pmpaddr0, pmpcfg0, sat
 
 use crate::mm::init_mm;
 use crate::syscall::syscall;
-use crate::timer::{get_time, init_timer, TIME_INTERVAL};
+use crate::timer::{get_time, init_realtime, init_timer, TIME_INTERVAL};
 
 mod lang_items;
 #[macro_use]
@@ -26,6 +27,7 @@ mod mm;
 mod task;
 mod loader;
 mod timer;
+mod tty;
 
 global_asm!(include_str!("entry.asm"));
 global_asm!(include_str!("link_app.S"));
@@ -34,6 +36,8 @@ global_asm!(include_str!("link_app.S"));
 pub fn rust_main() -> ! {
     clear_bss();
     init_mm();
+    sbi::plic::init();
+    init_realtime();
     println!("Hello, world!");
     task::init_proc();
     println!("after initproc!");
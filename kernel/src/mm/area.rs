@@ -1,16 +1,27 @@
+use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
 
 use crate::mm::address::{PhysPageNum, VirtAddr, VirtPageNum};
 use crate::mm::area::MapType::Framed;
-use crate::mm::frame_allocator::{frame_alloc, FrameTracker};
-use crate::mm::page_table::{PageTable, PTEFlags};
+use crate::mm::frame_allocator::{frame_alloc, frame_ref_count, FrameTracker};
+use crate::mm::page_table::{page_num, PAGE_LEVELS, PageTable, PTEFlags};
+use crate::mm::swap::{slot_alloc, SwapSlot};
 
 const PAGE_SIZE: usize = 0x1000;
 
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum FaultResult {
+    Handled,
+    Invalid, // a real fault
+    OutOfMemory,
+    StackOverflow, // below the largest user stack
+}
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum MapType {
     Identical,
     Framed,
+    Shared, // frames of a shared memory segment
 }
 
 bitflags! {
@@ -25,9 +36,16 @@ bitflags! {
 pub struct MapArea {
     vpn_beg: VirtPageNum,
     vpn_end: VirtPageNum,
-    data_frames: Vec<FrameTracker>,
+    data_frames: BTreeMap<VirtPageNum, FrameTracker>,
     map_type: MapType,
     map_perm: MapPermission,
+    // (start address, bytes) of file contents, copied into the pages on the first touch
+    // a page shared by two ELF segments takes a piece of each
+    files: Vec<(usize, &'static [u8])>,
+    // pages in the swap area
+    swapped: BTreeMap<VirtPageNum, SwapSlot>,
+    // slots of swapped in pages, still valid until the pages get dirty
+    swap_cache: BTreeMap<VirtPageNum, SwapSlot>,
 }
 
 impl MapArea {
@@ -36,9 +54,12 @@ impl MapArea {
         Self {
             vpn_beg: start_va.floor(),
             vpn_end: end_va.ceil(),
-            data_frames: Vec::new(),
+            data_frames: BTreeMap::new(),
             map_type: map_type,
             map_perm: map_perm,
+            files: Vec::new(),
+            swapped: BTreeMap::new(),
+            swap_cache: BTreeMap::new(),
         }
     }
 
@@ -46,37 +67,62 @@ impl MapArea {
         Self {
             vpn_beg: obj.vpn_beg,
             vpn_end: obj.vpn_end,
-            data_frames: Vec::new(),
+            data_frames: BTreeMap::new(),
             map_type: obj.map_type,
             map_perm: obj.map_perm,
+            files: obj.files.clone(),
+            swapped: BTreeMap::new(),
+            swap_cache: BTreeMap::new(),
         }
     }
 
-    pub fn map(&mut self, page_table: &mut PageTable) {
+    // return None if frames run out, pages mapped so far are left to the caller
+    pub fn map(&mut self, page_table: &mut PageTable) -> Option<()> {
+        if self.is_lazy() {
+            return Some(()); // user pages are allocated on the first touch
+        }
         let mut tmp = self.vpn_beg.0;
         while tmp < self.vpn_end.0 {
             let ppn: PhysPageNum;
+            let mut level = 0;
             match self.map_type {
                 MapType::Identical => {
+                    level = self.huge_level(tmp);
                     ppn = PhysPageNum(tmp);
                 }
                 Framed => {
-                    let frame = frame_alloc().unwrap();
+                    let frame = frame_alloc()?;
                     ppn = frame.ppn;
-                    self.data_frames.push(frame);
+                    self.data_frames.insert(VirtPageNum::from(tmp), frame);
+                }
+                MapType::Shared => {
+                    ppn = self.data_frames.get(&VirtPageNum::from(tmp)).unwrap().ppn;
                 }
             }
             let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
-            page_table.map(VirtPageNum::from(tmp), ppn, pte_flags);
-            tmp += 1;
+            page_table.map_huge(VirtPageNum::from(tmp), ppn, pte_flags, level)?;
+            tmp += page_num(level);
         }
+        Some(())
+    }
+
+    // the largest page that fits the identical mapping at vpn
+    fn huge_level(&self, vpn: usize) -> usize {
+        (0..PAGE_LEVELS).rev()
+            .find(|&level| vpn % page_num(level) == 0 && vpn + page_num(level) <= self.vpn_end.0)
+            .unwrap()
     }
 
     pub fn unmap(&mut self, page_table: &mut PageTable) {
         let mut tmp = self.vpn_beg.0;
         while tmp < self.vpn_end.0 {
-            page_table.unmap(VirtPageNum::from(tmp));
-            tmp += 1;
+            match page_table.find_leaf(VirtPageNum::from(tmp)).map(|(_, level)| level) {
+                Some(level) => {
+                    page_table.unmap(VirtPageNum::from(tmp));
+                    tmp = (tmp | (page_num(level) - 1)) + 1; // to the end of the (huge) page
+                }
+                None => tmp += 1, // lazy pages may be absent
+            }
         }
     }
 
@@ -99,6 +145,265 @@ impl MapArea {
         }
     }
 
+    // share all frames of obj, private frames are mapped read-only in both and copied on the first write
+    pub fn share_from(obj: &mut Self, page_table: &mut PageTable, obj_page_table: &mut PageTable) -> Option<Self> {
+        assert_ne!(obj.map_type, MapType::Identical);
+        let mut map_area = Self::new_from_exist(obj);
+        obj.swap_cache.clear(); // remapping clears the dirty bits
+        for (vpn, frame) in obj.data_frames.iter() {
+            if let Some(mut pte_flags) = obj.pte_flags() {
+                if obj.map_type == MapType::Framed {
+                    pte_flags.remove(PTEFlags::W);
+                    obj_page_table.map(*vpn, frame.ppn, pte_flags)?;
+                }
+                page_table.map(*vpn, frame.ppn, pte_flags)?;
+            }
+            map_area.data_frames.insert(*vpn, frame.share());
+        }
+        for (vpn, slot) in obj.swapped.iter() { // the slot is kept by obj, the new area reads its own copy
+            let frame = frame_alloc()?;
+            slot.read(frame.ppn);
+            if let Some(pte_flags) = obj.pte_flags() {
+                page_table.map(*vpn, frame.ppn, pte_flags)?;
+            }
+            map_area.data_frames.insert(*vpn, frame);
+        }
+        Some(map_area)
+    }
+
+    // frames of a shared memory segment, must be set before mapping
+    pub fn set_shared_frames(&mut self, frames: Vec<FrameTracker>) {
+        assert_eq!(self.map_type, MapType::Shared);
+        let mut vpn = self.vpn_beg;
+        for frame in frames {
+            self.data_frames.insert(vpn, frame);
+            vpn.next();
+        }
+    }
+
+    pub fn add_file(&mut self, start_va: VirtAddr, data: &'static [u8]) {
+        self.files.push((start_va.into(), data));
+    }
+
+    // copy the file contents falling in the page of vpn
+    fn load_file(&self, vpn: VirtPageNum, dst: &mut [u8]) {
+        let page_beg: usize = VirtAddr::from(vpn).into();
+        for &(file_beg, data) in self.files.iter() {
+            let beg = page_beg.max(file_beg);
+            let end = (page_beg + PAGE_SIZE).min(file_beg + data.len());
+            if beg < end {
+                dst[beg - page_beg..end - page_beg].copy_from_slice(&data[beg - file_beg..end - file_beg]);
+            }
+        }
+    }
+
+    // handle a page fault of the given access (R/W/X)
+    pub fn handle_page_fault(&mut self, page_table: &mut PageTable, vpn: VirtPageNum, access: MapPermission) -> FaultResult {
+        if !self.map_perm.contains(access) || !self.is_lazy() {
+            return FaultResult::Invalid;
+        }
+        if self.data_frames.contains_key(&vpn) {
+            return if access == MapPermission::W {
+                self.copy_on_write(page_table, vpn)
+            } else {
+                FaultResult::Invalid
+            };
+        }
+        let frame = match frame_alloc() {
+            Some(frame) => frame,
+            None => return FaultResult::OutOfMemory,
+        };
+        if let Some(slot) = self.swapped.remove(&vpn) {
+            slot.read(frame.ppn);
+            self.swap_cache.insert(vpn, slot);
+        } else {
+            self.load_file(vpn, frame.ppn.get_bytes_array());
+        }
+        // set the accessed bit, so the new page survives the next scan of the clock
+        if page_table.map(vpn, frame.ppn, PTEFlags::from_bits(self.map_perm.bits).unwrap() | PTEFlags::A).is_none() {
+            if let Some(slot) = self.swap_cache.remove(&vpn) {
+                self.swapped.insert(vpn, slot); // the page is still in swap area
+            }
+            return FaultResult::OutOfMemory;
+        }
+        self.data_frames.insert(vpn, frame);
+        FaultResult::Handled
+    }
+
+    // copy a page for a core dump without faulting it in, pages never touched read as zero
+    pub fn read_page(&self, vpn: VirtPageNum, dst: &mut [u8]) {
+        if let Some(frame) = self.data_frames.get(&vpn) {
+            dst.copy_from_slice(frame.ppn.get_bytes_array());
+        } else if let Some(slot) = self.swapped.get(&vpn) {
+            if let Some(frame) = frame_alloc() { // left zero if frames run out
+                slot.read(frame.ppn);
+                dst.copy_from_slice(frame.ppn.get_bytes_array());
+            }
+        } else {
+            self.load_file(vpn, dst);
+        }
+    }
+
+    // handle a store to a page which is shared by COW
+    pub fn copy_on_write(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> FaultResult {
+        if self.map_type != MapType::Framed || !self.map_perm.contains(MapPermission::W) {
+            return FaultResult::Invalid;
+        }
+        let src_ppn = match self.data_frames.get(&vpn) {
+            Some(frame) => frame.ppn,
+            None => return FaultResult::Invalid,
+        };
+        if page_table.translate(vpn).map_or(true, |pte| pte.writable()) {
+            return FaultResult::Invalid;
+        }
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        self.swap_cache.remove(&vpn);
+        let ppn = if frame_ref_count(src_ppn) == 1 { // the other owners are gone, take the frame back
+            src_ppn
+        } else {
+            let frame = match frame_alloc() {
+                Some(frame) => frame,
+                None => return FaultResult::OutOfMemory,
+            };
+            frame.ppn.get_bytes_array().copy_from_slice(src_ppn.get_bytes_array());
+            let ppn = frame.ppn;
+            self.data_frames.insert(vpn, frame); // drop the shared one
+            ppn
+        };
+        match page_table.map(vpn, ppn, pte_flags) {
+            Some(_) => FaultResult::Handled,
+            None => FaultResult::OutOfMemory,
+        }
+    }
+
+    // move the end of the area, frames beyond the new end are released
+    pub fn set_end(&mut self, page_table: &mut PageTable, vpn_end: VirtPageNum) {
+        let released = self.data_frames.split_off(&vpn_end);
+        for vpn in released.keys() {
+            if page_table.translate(*vpn).is_some() {
+                page_table.unmap(*vpn);
+            }
+        }
+        self.swapped.split_off(&vpn_end);
+        self.swap_cache.split_off(&vpn_end);
+        self.vpn_end = vpn_end;
+    }
+
+    // extend a lazy area downward to vpn_beg, the new pages come on the first touch
+    pub fn set_beg(&mut self, vpn_beg: VirtPageNum) {
+        assert!(self.is_lazy() && vpn_beg <= self.vpn_beg);
+        self.vpn_beg = vpn_beg;
+    }
+
+    // split the area at vpn, self keeps [vpn_beg, vpn) and the rest is returned
+    pub fn split_off(&mut self, vpn: VirtPageNum) -> Self {
+        let mut map_area = Self::new_from_exist(self);
+        map_area.vpn_beg = vpn;
+        map_area.data_frames = self.data_frames.split_off(&vpn);
+        map_area.swapped = self.swapped.split_off(&vpn);
+        map_area.swap_cache = self.swap_cache.split_off(&vpn);
+        self.vpn_end = vpn;
+        map_area
+    }
+
+    pub fn can_merge(&self, other: &Self) -> bool {
+        self.vpn_end == other.vpn_beg && self.map_perm == other.map_perm &&
+            self.is_lazy() && other.is_lazy() && self.files.is_empty() && other.files.is_empty()
+    }
+
+    pub fn merge(&mut self, mut other: Self) {
+        self.data_frames.append(&mut other.data_frames);
+        self.swapped.append(&mut other.swapped);
+        self.swap_cache.append(&mut other.swap_cache);
+        self.vpn_end = other.vpn_end;
+    }
+
+    // change the permission of present pages, frames shared by COW stay read-only
+    // the present pages keep their page table entries, so remapping them cannot fail
+    pub fn set_perm(&mut self, page_table: &mut PageTable, map_perm: MapPermission) {
+        self.map_perm = map_perm;
+        self.swap_cache.clear(); // remapping clears the dirty bits
+        for (vpn, frame) in self.data_frames.iter() {
+            match self.pte_flags() {
+                Some(mut pte_flags) => {
+                    if frame_ref_count(frame.ppn) > 1 {
+                        pte_flags.remove(PTEFlags::W);
+                    }
+                    page_table.map(*vpn, frame.ppn, pte_flags).unwrap();
+                }
+                None => {
+                    if page_table.translate(*vpn).is_some() {
+                        page_table.unmap(*vpn);
+                    }
+                }
+            }
+        }
+    }
+
+    // clock scan of present private pages from vpn: clear the accessed bits, and swap out the first
+    // page which has not been accessed since the last scan
+    pub fn swap_out(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> Option<VirtPageNum> {
+        if !self.is_lazy() {
+            return None;
+        }
+        let victim = *self.data_frames.range(vpn..).find(|(vpn, frame)| {
+            match page_table.find_pte(**vpn) {
+                Some(pte) if frame_ref_count(frame.ppn) == 1 => { // frames shared by COW stay in memory
+                    if pte.accessed() {
+                        pte.clear_accessed();
+                        false
+                    } else {
+                        true
+                    }
+                }
+                _ => false, // not mapped (e.g. guard pages)
+            }
+        })?.0;
+        let dirty = page_table.translate(victim).unwrap().dirty();
+        let slot = match self.swap_cache.remove(&victim) {
+            Some(slot) if !dirty => slot, // the copy in swap area is up to date
+            slot => {
+                let slot = slot.or_else(slot_alloc)?;
+                slot.write(self.data_frames.get(&victim).unwrap().ppn);
+                slot
+            }
+        };
+        page_table.unmap(victim);
+        self.data_frames.remove(&victim);
+        self.swapped.insert(victim, slot);
+        Some(victim)
+    }
+
+    // kernel writes user pages through physical addresses, which does not set the dirty bits
+    pub fn mark_dirty(&mut self, vpn: VirtPageNum) {
+        self.swap_cache.remove(&vpn);
+    }
+
+    // flags of present pages, None if the area cannot be accessed at all (e.g. guard pages)
+    fn pte_flags(&self) -> Option<PTEFlags> {
+        if (self.map_perm & (MapPermission::R | MapPermission::W | MapPermission::X)).is_empty() {
+            None
+        } else {
+            PTEFlags::from_bits(self.map_perm.bits)
+        }
+    }
+
+    pub fn frame_num(&self) -> usize {
+        self.data_frames.len()
+    }
+
+    pub fn is_lazy(&self) -> bool {
+        self.map_type == MapType::Framed && self.map_perm.contains(MapPermission::U)
+    }
+
+    pub fn is_shared(&self) -> bool {
+        self.map_type == MapType::Shared
+    }
+
+    pub fn contains(&self, vpn: VirtPageNum) -> bool { self.vpn_beg <= vpn && vpn < self.vpn_end }
+
+    pub fn get_perm(&self) -> MapPermission { self.map_perm }
+
     pub fn get_beg_vpn(&self) -> VirtPageNum { self.vpn_beg }
 
     pub fn get_end_vpn(&self) -> VirtPageNum { self.vpn_end }
@@ -0,0 +1,76 @@
+use core::arch::asm;
+
+use lazy_static::lazy_static;
+use riscv::register::satp;
+
+use crate::sync::safe_cell_single::SafeCellSingle;
+
+pub const ASID_SHIFT: usize = 44;
+const ASID_MASK: usize = 0xffff;
+
+// ASID of a memory set, valid only in the generation it was assigned in
+pub struct Asid {
+    generation: usize,
+    asid: usize,
+}
+
+pub struct AsidManager {
+    // bumped when ASIDs run out, all the old ones become invalid then
+    generation: usize,
+    next: usize,
+    // 0 if the hart has no ASID bits
+    max: usize,
+}
+
+impl Asid {
+    pub fn new() -> Self {
+        Self { generation: 0, asid: 0 }
+    }
+
+    pub fn get(&self) -> usize {
+        self.asid
+    }
+}
+
+impl AsidManager {
+    pub fn new() -> Self {
+        Self { generation: 1, next: 1, max: 0 } // ASID 0 is kept for kernel space
+    }
+
+    // assign a new ASID to asid unless it still holds one of this generation
+    pub fn refresh(&mut self, asid: &mut Asid) {
+        if self.max == 0 || asid.generation == self.generation {
+            return;
+        }
+        if self.next > self.max { // rollover
+            self.generation += 1;
+            self.next = 1;
+            unsafe { asm!("sfence.vma"); }
+        }
+        asid.generation = self.generation;
+        asid.asid = self.next;
+        self.next += 1;
+    }
+}
+
+lazy_static! {
+    pub static ref ASID_MANAGER: SafeCellSingle<AsidManager> = unsafe { SafeCellSingle::new(AsidManager::new()) };
+}
+
+// ASID bits are WARL, so writing all ones reads back the implemented ones
+pub fn init_asid() {
+    let old = satp::read().bits();
+    unsafe {
+        satp::write(old | ASID_MASK << ASID_SHIFT);
+        let max = satp::read().bits() >> ASID_SHIFT & ASID_MASK;
+        satp::write(old);
+        asm!("sfence.vma");
+        ASID_MANAGER.borrow_exclusive().max = max;
+        println!("[kernel] asid: {} available", max);
+    }
+}
+
+// flush TLB entries of a single address space
+pub fn flush_asid(asid: usize) {
+    unsafe { asm!("sfence.vma zero, {}", in(reg) asid); }
+}
@@ -2,6 +2,9 @@ use core::alloc::{GlobalAlloc, Layout};
 use core::cmp::{max, min};
 use core::ptr::null_mut;
 
+use crate::mm::heap::Heap;
+use crate::mm::slab::SlabAllocator;
+
 pub const BLOCK_UNIT_SIZE: usize = 0x1000;
 pub const BLOCK_LEVEL: usize = 11;
 pub const TABLE_SIZE: usize = 1024;
@@ -26,10 +29,17 @@ pub struct Allocator {
     link_table: [LinkNode; TABLE_SIZE],
     // begin address of the heap
     heap_beg_addr: usize,
+    // level of the whole heap
+    level: usize,
+    // units in use
+    used: usize,
 }
 
 pub struct AllocatorWrap {
+    // the heap made of buddy arenas
     pub allocator: usize,
+    // small layouts are served by the slab allocator
+    pub slab: usize,
 }
 
 impl Allocator {
@@ -40,21 +50,73 @@ impl Allocator {
             free_head: free_head,
             link_table: link_table,
             heap_beg_addr: 0,
+            level: 0,
+            used: 0,
         };
         allocator
     }
 
-    pub fn init(&mut self, _heap_beg_addr: usize) {
+    pub fn init(&mut self, _heap_beg_addr: usize, _level: usize) {
+        assert!(_level < BLOCK_LEVEL);
         self.heap_beg_addr = _heap_beg_addr;
-        self.push(0, BLOCK_LEVEL - 1);
+        self.level = _level;
+        self.used = 0;
+        self.free_head = [-1i16; BLOCK_LEVEL];
+        self.push(0, _level);
+    }
+
+    // forget the heap, it must be empty
+    pub fn clear(&mut self) {
+        assert!(self.is_empty());
+        self.heap_beg_addr = 0;
+    }
+
+    pub fn alloc(&mut self, level: usize) -> *mut u8 {
+        if level > self.level {
+            return null_mut();
+        }
+        let ptr = self.split(level);
+        if !ptr.is_null() {
+            self.used += 1 << level;
+        }
+        ptr
+    }
+
+    pub fn dealloc(&mut self, addr: usize, level: usize) {
+        self.merge(addr, level);
+        self.used -= 1 << level;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.heap_beg_addr != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.used == 0
+    }
+
+    pub fn contains(&self, addr: usize) -> bool {
+        self.is_active() && self.heap_beg_addr <= addr && addr < self.heap_beg_addr + self.size()
+    }
+
+    pub fn beg_addr(&self) -> usize {
+        self.heap_beg_addr
+    }
+
+    pub fn size(&self) -> usize {
+        (1 << self.level) * BLOCK_UNIT_SIZE
+    }
+
+    pub fn used_size(&self) -> usize {
+        self.used * BLOCK_UNIT_SIZE
     }
 
 
     fn merge(&mut self, addr: usize, level: usize) {
-        if level >= BLOCK_LEVEL {
+        if level > self.level {
             panic!("[kernel]: Unknown error when alloca.");
         }
-        if level == BLOCK_LEVEL - 1 {
+        if level == self.level {
             self.push(self.get_link_index(addr), level);
             return;
         }
@@ -77,7 +139,7 @@ impl Allocator {
     fn split(&mut self, level: usize) -> *mut u8 {
         let mut now_level: usize = level;
         let mut index: usize = 0;
-        while now_level < BLOCK_LEVEL {
+        while now_level <= self.level {
             if self.free_head[now_level] != -1 {
                 index = self.free_head[now_level] as usize;
                 self.pop(index);
@@ -85,7 +147,7 @@ impl Allocator {
             }
             now_level += 1;
         }
-        if now_level == BLOCK_LEVEL {
+        if now_level > self.level {
             return null_mut();
         }
         while now_level > level {
@@ -132,28 +194,37 @@ impl Allocator {
 
 impl AllocatorWrap {
     pub const fn empty() -> Self {
-        AllocatorWrap { allocator: 0 }
+        AllocatorWrap { allocator: 0, slab: 0 }
     }
 
-    pub unsafe fn init(&mut self, allocator: &mut Allocator) {
-        self.allocator = allocator as *mut Allocator as usize;
+    pub unsafe fn init(&mut self, allocator: &mut Heap, slab: &mut SlabAllocator) {
+        self.allocator = allocator as *mut Heap as usize;
+        self.slab = slab as *mut SlabAllocator as usize;
     }
 }
 
+// level of the block serving the layout
+fn level_of(_layout: Layout) -> usize {
+    let size = max(max(_layout.size().next_power_of_two(), BLOCK_UNIT_SIZE), _layout.align());
+    (size / BLOCK_UNIT_SIZE).trailing_zeros() as usize
+}
+
 unsafe impl GlobalAlloc for AllocatorWrap {
     unsafe fn alloc(&self, _layout: Layout) -> *mut u8 {
-        let size = max(max(_layout.size().next_power_of_two(), BLOCK_UNIT_SIZE), _layout.align());
-        let alloctor = &mut *(self.allocator as *mut Allocator);
-        alloctor.split((size / BLOCK_UNIT_SIZE).trailing_zeros() as usize)
+        let heap = &mut *(self.allocator as *mut Heap);
+        if let Some(class) = SlabAllocator::class_of(_layout.size(), _layout.align()) {
+            let slab = &mut *(self.slab as *mut SlabAllocator);
+            return slab.alloc(class, heap);
+        }
+        heap.alloc(level_of(_layout))
     }
 
     unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
-        let alloctor = &mut *(self.allocator as *mut Allocator);
-        if alloctor.heap_beg_addr > _ptr as usize ||
-            alloctor.heap_beg_addr + KERNEL_HEAP_SIZE <= _ptr as usize {
-            panic!("[kernel]: Invalid address to dealloc.");
+        let heap = &mut *(self.allocator as *mut Heap);
+        if let Some(class) = SlabAllocator::class_of(_layout.size(), _layout.align()) {
+            let slab = &mut *(self.slab as *mut SlabAllocator);
+            return slab.dealloc(_ptr, class, heap);
         }
-        let size = max(max(_layout.size().next_power_of_two(), BLOCK_UNIT_SIZE), _layout.align());
-        alloctor.merge(_ptr as usize, (size / BLOCK_UNIT_SIZE).trailing_zeros() as usize);
+        heap.dealloc(_ptr as usize, level_of(_layout));
     }
 }
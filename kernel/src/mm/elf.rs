@@ -0,0 +1,88 @@
+use alloc::vec::Vec;
+
+use xmas_elf::dynamic::Tag;
+use xmas_elf::header::{Class, Data, Machine, Type};
+use xmas_elf::program::{ProgramHeader, SegmentData};
+use xmas_elf::ElfFile;
+
+pub const PIE_BASE: usize = 0x1000_0000; // load base of position-independent executables
+pub const PT_GNU_STACK: u32 = 0x6474_e551;
+const R_RISCV_NONE: u32 = 0;
+const R_RISCV_RELATIVE: u32 = 3;
+const RELA_SIZE: usize = 24;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ElfError {
+    Malformed, // cannot be parsed, or offsets beyond the file
+    BadHeader, // not an executable of RV64 in little endian
+    BadSegment, // overlapping, out of user space, or needing an interpreter
+    BadEntry, // entry point not in an executable segment
+    BadRelocation, // not R_RISCV_RELATIVE, or out of the segments
+    OutOfMemory,
+}
+
+// check the header, return the load base
+pub fn check_header(elf: &ElfFile) -> Result<usize, ElfError> {
+    let header = elf.header;
+    if header.pt1.class() != Class::SixtyFour || header.pt1.data() != Data::LittleEndian ||
+        header.pt2.machine().as_machine() != Machine::RISC_V {
+        return Err(ElfError::BadHeader);
+    }
+    match header.pt2.type_().as_type() {
+        Type::Executable => Ok(0),
+        Type::SharedObject => Ok(PIE_BASE),
+        _ => Err(ElfError::BadHeader),
+    }
+}
+
+// (offset, addend) of R_RISCV_RELATIVE relocations, both relative to the load base
+pub fn relocations(elf: &ElfFile) -> Result<Vec<(usize, usize)>, ElfError> {
+    let mut relocations = Vec::new();
+    let dynamic = match elf.program_iter().find(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Dynamic)) {
+        Some(ph) => ph,
+        None => return Ok(relocations), // a static executable
+    };
+    let entries = match dynamic.get_data(elf) {
+        Ok(SegmentData::Dynamic64(entries)) => entries,
+        _ => return Err(ElfError::Malformed),
+    };
+    let (mut rela, mut rela_size) = (None, 0);
+    for entry in entries {
+        match entry.get_tag() {
+            Ok(Tag::Rela) => rela = Some(entry.get_ptr().map_err(|_| ElfError::Malformed)? as usize),
+            Ok(Tag::RelaSize) => rela_size = entry.get_val().map_err(|_| ElfError::Malformed)? as usize,
+            _ => {}
+        }
+    }
+    let rela = match rela {
+        Some(rela) => rela,
+        None => return Ok(relocations),
+    };
+    let offset = file_offset(elf, rela, rela_size).ok_or(ElfError::Malformed)?;
+    for entry in elf.input[offset..offset + rela_size].chunks_exact(RELA_SIZE) {
+        let word = |i: usize| u64::from_le_bytes(entry[i * 8..i * 8 + 8].try_into().unwrap()) as usize;
+        match word(1) as u32 {
+            R_RISCV_NONE => {}
+            R_RISCV_RELATIVE => relocations.push((word(0), word(2))),
+            _ => return Err(ElfError::BadRelocation), // symbols are not resolved
+        }
+    }
+    Ok(relocations)
+}
+
+// file offset of the bytes at va, which must lie in the file part of a loaded segment
+fn file_offset(elf: &ElfFile, va: usize, len: usize) -> Option<usize> {
+    elf.program_iter()
+        .filter(|ph| ph.get_type() == Ok(xmas_elf::program::Type::Load))
+        .find(|ph| segment_contains(ph, va, len))
+        .and_then(|ph| (ph.offset() as usize).checked_add(va - ph.virtual_addr() as usize))
+        .filter(|&offset| offset.checked_add(len).map_or(false, |end| end <= elf.input.len()))
+}
+
+fn segment_contains(ph: &ProgramHeader, va: usize, len: usize) -> bool {
+    let beg = ph.virtual_addr() as usize;
+    match (va.checked_add(len), beg.checked_add(ph.file_size() as usize)) {
+        (Some(end), Some(file_end)) => beg <= va && end <= file_end,
+        _ => false,
+    }
+}
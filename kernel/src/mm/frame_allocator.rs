@@ -1,9 +1,11 @@
+use alloc::vec;
 use alloc::vec::Vec;
 use core::borrow::BorrowMut;
 
 use lazy_static::lazy_static;
 
 use crate::mm::address::{PhysAddr, PhysPageNum};
+use crate::mm::swap::swap_out;
 use crate::sync::safe_cell_single;
 use crate::sync::safe_cell_single::SafeCellSingle;
 
@@ -13,61 +15,135 @@ pub const BUFFER_BEG: usize = 0x87E0_0000;
 trait FrameAllocator {
     fn new() -> Self;
     fn alloc(&mut self) -> Option<PhysPageNum>;
+    fn alloc_contiguous(&mut self, num: usize, align: usize) -> Option<PhysPageNum>;
     fn dealloc(&mut self, ppn: PhysPageNum);
+    fn add_ref(&mut self, ppn: PhysPageNum);
+    fn ref_count(&self, ppn: PhysPageNum) -> usize;
+    fn free_num(&self) -> usize;
+    fn total_num(&self) -> usize;
 }
 
 pub struct FrameTracker {
     pub ppn: PhysPageNum,
 }
 
-pub struct StackFrameAllocator {
-    // free frames which have never been used
-    beg: usize,
-    end: usize,
-    // recycle frames
-    recycled: Vec<usize>,
+pub struct BitmapFrameAllocator {
+    // first frame managed by the allocator
+    base: usize,
+    total: usize,
+    free: usize,
+    // a set bit represents an allocated frame
+    bitmap: Vec<u64>,
+    // word where the next search begins
+    hint: usize,
+    // number of owners of each frame (frames may be shared after fork)
+    ref_count: Vec<u32>,
 }
 
-impl FrameAllocator for StackFrameAllocator {
+impl FrameAllocator for BitmapFrameAllocator {
     fn new() -> Self {
         Self {
-            beg: 0,
-            end: 0,
-            recycled: Vec::new(),
+            base: 0,
+            total: 0,
+            free: 0,
+            bitmap: Vec::new(),
+            hint: 0,
+            ref_count: Vec::new(),
         }
     }
 
     fn alloc(&mut self) -> Option<PhysPageNum> {
-        if let Some(ppn) = self.recycled.pop() {
-            Some(ppn.into())
-        } else {
-            if self.beg == self.end {
-                None
-            } else {
-                self.beg += 1;
-                Some((self.beg - 1).into())
+        if self.free == 0 {
+            return None;
+        }
+        let words = self.bitmap.len();
+        let word = (0..words).map(|i| (self.hint + i) % words).find(|&i| self.bitmap[i] != u64::MAX)?;
+        let index = word * 64 + self.bitmap[word].trailing_ones() as usize;
+        self.hint = word;
+        self.set_used(index, 1);
+        Some((self.base + index).into())
+    }
+
+    // num frames beginning at a multiple of align
+    fn alloc_contiguous(&mut self, num: usize, align: usize) -> Option<PhysPageNum> {
+        assert!(num > 0 && align.is_power_of_two());
+        if self.free < num {
+            return None;
+        }
+        let mut ppn = (self.base + align - 1) & !(align - 1);
+        while ppn + num <= self.base + self.total {
+            let beg = ppn - self.base;
+            match (beg..beg + num).rev().find(|&index| self.is_used(index)) {
+                Some(index) => ppn = (self.base + index + align) & !(align - 1), // skip the used frame
+                None => {
+                    self.set_used(beg, num);
+                    return Some(ppn.into());
+                }
             }
         }
+        None
     }
 
     fn dealloc(&mut self, ppn: PhysPageNum) {
-        let ppn = ppn.into();
-        if ppn >= self.beg ||
-            self.recycled.iter().find(|&v| { *v == ppn }).is_some() {
+        let ppn: usize = ppn.into();
+        if ppn < self.base || ppn >= self.base + self.total || !self.is_used(ppn - self.base) {
             panic!("[Kernel]: Frame ppn={:#x} has not been allocated!", ppn);
         }
-        self.recycled.push(ppn);
+        let index = ppn - self.base;
+        self.ref_count[index] -= 1;
+        if self.ref_count[index] == 0 { // last owner is gone
+            self.bitmap[index / 64] &= !(1 << (index % 64));
+            self.free += 1;
+        }
+    }
+
+    fn add_ref(&mut self, ppn: PhysPageNum) {
+        let index = ppn.0 - self.base;
+        assert!(self.is_used(index) && self.ref_count[index] > 0);
+        // a wrapped count would free the frame while it is still mapped
+        self.ref_count[index] = self.ref_count[index].checked_add(1).expect("[Kernel]: Too many owners of a frame");
+    }
+
+    fn ref_count(&self, ppn: PhysPageNum) -> usize {
+        self.ref_count[ppn.0 - self.base] as usize
+    }
+
+    fn free_num(&self) -> usize {
+        self.free
+    }
+
+    fn total_num(&self) -> usize {
+        self.total
     }
 }
 
-impl StackFrameAllocator {
+impl BitmapFrameAllocator {
     pub fn init(&mut self, _beg: PhysPageNum, _end: PhysPageNum) {
-        self.beg = _beg.0;
-        self.end = _end.0;
+        self.base = _beg.0;
+        self.total = _end.0 - _beg.0;
+        self.free = self.total;
+        self.bitmap = vec![0; (self.total + 63) / 64];
+        if self.total % 64 != 0 { // frames beyond the end are never handed out
+            *self.bitmap.last_mut().unwrap() = !0 << (self.total % 64);
+        }
+        self.hint = 0;
+        self.ref_count = vec![0; self.total];
+    }
+
+    fn is_used(&self, index: usize) -> bool {
+        self.bitmap[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    fn set_used(&mut self, beg: usize, num: usize) {
+        for index in beg..beg + num {
+            self.bitmap[index / 64] |= 1 << (index % 64);
+            self.ref_count[index] = 1;
+        }
+        self.free -= num;
     }
 }
 
-type FrameAllocatorImpl = StackFrameAllocator;
+type FrameAllocatorImpl = BitmapFrameAllocator;
 lazy_static! {
     pub static ref FRAME_ALLOCATOR: SafeCellSingle<FrameAllocatorImpl> = unsafe {
         SafeCellSingle::new(FrameAllocatorImpl::new())
@@ -82,6 +158,12 @@ impl FrameTracker {
         }
         Self { ppn }
     }
+
+    // another owner of the same frame, without cleaning it (used by COW)
+    pub fn share(&self) -> Self {
+        frame_add_ref(self.ppn);
+        Self { ppn: self.ppn }
+    }
 }
 
 impl Drop for FrameTracker {
@@ -99,11 +181,31 @@ pub fn init_frame_allocator() {
         .init(PhysAddr::from(ekernel as usize).ceil(), PhysAddr::from(BUFFER_BEG).floor());
 }
 
+// when frames run out, a user page is swapped out to make room
 pub fn frame_alloc() -> Option<FrameTracker> {
+    let ppn = FRAME_ALLOCATOR.borrow_exclusive().alloc();
+    ppn.or_else(|| if swap_out(None) { FRAME_ALLOCATOR.borrow_exclusive().alloc() } else { None })
+        .map(|ppn| FrameTracker::new(ppn))
+}
+
+// physically contiguous frames (e.g. for DMA), the first one is aligned to align frames
+pub fn frame_alloc_contiguous(num: usize, align: usize) -> Option<Vec<FrameTracker>> {
+    let beg = frame_alloc_pages(num, align)?;
+    Some((beg.0..beg.0 + num).map(|ppn| FrameTracker::new(ppn.into())).collect())
+}
+
+// untracked frames for the kernel heap, which must not allocate on the heap itself
+pub fn frame_alloc_pages(num: usize, align: usize) -> Option<PhysPageNum> {
     FRAME_ALLOCATOR
         .borrow_exclusive()
-        .alloc()
-        .map(|ppn| FrameTracker::new(ppn))
+        .alloc_contiguous(num, align)
+}
+
+pub fn frame_dealloc_pages(beg: PhysPageNum, num: usize) {
+    let mut allocator = FRAME_ALLOCATOR.borrow_exclusive();
+    for ppn in beg.0..beg.0 + num {
+        allocator.dealloc(ppn.into());
+    }
 }
 
 pub fn frame_dealloc(ppn: PhysPageNum) {
@@ -111,3 +213,27 @@ pub fn frame_dealloc(ppn: PhysPageNum) {
         .borrow_exclusive()
         .dealloc(ppn);
 }
+
+pub fn frame_add_ref(ppn: PhysPageNum) {
+    FRAME_ALLOCATOR
+        .borrow_exclusive()
+        .add_ref(ppn);
+}
+
+pub fn frame_ref_count(ppn: PhysPageNum) -> usize {
+    FRAME_ALLOCATOR
+        .borrow_exclusive()
+        .ref_count(ppn)
+}
+
+pub fn frame_free_num() -> usize {
+    FRAME_ALLOCATOR
+        .borrow_exclusive()
+        .free_num()
+}
+
+pub fn frame_total_num() -> usize {
+    FRAME_ALLOCATOR
+        .borrow_exclusive()
+        .total_num()
+}
@@ -0,0 +1,107 @@
+use core::cmp::max;
+use core::ptr::null_mut;
+
+use crate::mm::address::{PhysAddr, PhysPageNum};
+use crate::mm::buddy::{Allocator, BLOCK_LEVEL, BLOCK_UNIT_SIZE};
+use crate::mm::frame_allocator::{frame_alloc_pages, frame_dealloc_pages};
+use crate::timer::get_time_ms;
+
+pub const MAX_ARENA_NUM: usize = 16;
+// arenas taken from the frame allocator are 1 MiB, unless a larger block is asked for
+const ARENA_LEVEL: usize = 8;
+// an empty arena idle for so long goes back to the frame allocator
+const ARENA_IDLE_MS: usize = 1000;
+
+const EMPTY_ARENA: Allocator = Allocator::empty();
+
+// the kernel heap, arenas[0] is in .bss and the others are frames (identically mapped in kernel space)
+pub struct Heap {
+    arenas: [Allocator; MAX_ARENA_NUM],
+    // when the arena became empty (0 while in use)
+    idle_since: [usize; MAX_ARENA_NUM],
+}
+
+impl Heap {
+    pub const fn empty() -> Self {
+        Heap {
+            arenas: [EMPTY_ARENA; MAX_ARENA_NUM],
+            idle_since: [0; MAX_ARENA_NUM],
+        }
+    }
+
+    pub fn init(&mut self, _heap_beg_addr: usize, _heap_size: usize) {
+        self.arenas[0].init(_heap_beg_addr, (_heap_size / BLOCK_UNIT_SIZE).trailing_zeros() as usize);
+    }
+
+    pub fn alloc(&mut self, level: usize) -> *mut u8 {
+        if level >= BLOCK_LEVEL {
+            return null_mut();
+        }
+        self.shrink();
+        for i in 0..MAX_ARENA_NUM {
+            if self.arenas[i].is_active() {
+                let ptr = self.arenas[i].alloc(level);
+                if !ptr.is_null() {
+                    self.idle_since[i] = 0;
+                    return ptr;
+                }
+            }
+        }
+        match self.grow(level) {
+            Some(i) => self.arenas[i].alloc(level),
+            None => null_mut(),
+        }
+    }
+
+    pub fn dealloc(&mut self, addr: usize, level: usize) {
+        let i = match (0..MAX_ARENA_NUM).find(|&i| self.arenas[i].contains(addr)) {
+            Some(i) => i,
+            None => panic!("[kernel]: Invalid address to dealloc."),
+        };
+        self.arenas[i].dealloc(addr, level);
+        if i > 0 && self.arenas[i].is_empty() {
+            self.idle_since[i] = max(get_time_ms(), 1);
+        }
+        self.shrink();
+    }
+
+    // take a new arena from the frame allocator, smaller ones are tried when memory is fragmented
+    fn grow(&mut self, level: usize) -> Option<usize> {
+        let i = (1..MAX_ARENA_NUM).find(|&i| !self.arenas[i].is_active())?;
+        for arena_level in (level..=max(level, ARENA_LEVEL)).rev() {
+            let pages = 1 << arena_level;
+            if let Some(ppn) = frame_alloc_pages(pages, pages) {
+                self.arenas[i].init(PhysAddr::from(ppn).0, arena_level);
+                return Some(i);
+            }
+        }
+        None
+    }
+
+    // hand the long-idle arenas back
+    fn shrink(&mut self) {
+        let now = get_time_ms();
+        for i in 1..MAX_ARENA_NUM {
+            if self.arenas[i].is_active() && self.arenas[i].is_empty()
+                && self.idle_since[i] != 0 && now - self.idle_since[i] >= ARENA_IDLE_MS {
+                let beg: PhysPageNum = PhysAddr::from(self.arenas[i].beg_addr()).floor();
+                frame_dealloc_pages(beg, self.arenas[i].size() / BLOCK_UNIT_SIZE);
+                self.arenas[i].clear();
+                self.idle_since[i] = 0;
+            }
+        }
+    }
+
+    pub fn print_stats(&self) {
+        for (i, arena) in self.arenas.iter().enumerate().filter(|(_, arena)| arena.is_active()) {
+            println!(
+                "[kernel] heap arena {}: [{:#x}, {:#x}), {} KiB used of {} KiB",
+                i,
+                arena.beg_addr(),
+                arena.beg_addr() + arena.size(),
+                arena.used_size() / 1024,
+                arena.size() / 1024
+            );
+        }
+    }
+}
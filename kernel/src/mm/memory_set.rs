@@ -1,4 +1,5 @@
 use alloc::collections::BTreeMap;
+use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::arch::asm;
@@ -6,10 +7,14 @@ use core::arch::asm;
 use lazy_static::lazy_static;
 use riscv::register::satp;
 
+use crate::mm::asid::{Asid, ASID_MANAGER, ASID_SHIFT, flush_asid};
+use crate::mm::elf::{check_header, ElfError, PT_GNU_STACK, relocations};
 use crate::mm::address::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
-use crate::mm::area::{MapArea, MapPermission, MapType};
-use crate::mm::frame_allocator::{BUFFER_BEG, frame_alloc, FrameTracker, MEMORY_END};
+use crate::mm::area::{FaultResult, MapArea, MapPermission, MapType};
+use crate::mm::frame_allocator::{BUFFER_BEG, frame_alloc, frame_free_num, FrameTracker, MEMORY_END};
 use crate::mm::page_table::{PageTable, PageTableEntry, PTEFlags};
+use crate::mm::swap::swap_out;
+use crate::mm::user_ptr::UserSlice;
 use crate::sync::safe_cell_single::SafeCellSingle;
 
 pub const TRAMPOLINE: usize = usize::MAX - 0x1000 + 1;
@@ -18,10 +23,24 @@ pub const BUFFER: usize = usize::MAX - 0x3000 + 1;
 pub const UART_BASE_ADDRESS: usize = 0x10_000_000;
 pub const PAGE_SIZE: usize = 0x1000;
 pub const USER_STACK_SIZE: usize = 0x2000;
+pub const USER_SPACE_END: usize = 0x40_0000_0000;
+pub const USER_STACK_TOP: usize = USER_SPACE_END - PAGE_SIZE; // top of the lower half of Sv39
+pub const USER_STACK_MAX_SIZE: usize = 0x80_0000; // the stack grows on page faults up to this size
+pub const ARG_MAX: usize = PAGE_SIZE; // argv and envp passed to exec, strings and pointers included
+const AT_NULL: usize = 0;
+const AT_PAGESZ: usize = 6;
+const STACK_GROW_GAP: usize = 16; // pages below the stack bottom that a fault may grow the stack to
+// the program break stays below the largest stack and its guard gap
+pub const HEAP_TOP: usize = USER_STACK_TOP - USER_STACK_MAX_SIZE - STACK_GROW_GAP * PAGE_SIZE;
+pub const MMAP_BASE: usize = 0x10_0000_0000;
+pub const MMAP_TOP: usize = 0x30_0000_0000;
+const SWAP_RESERVE_FRAMES: usize = 4; // a page fault may take a frame and some page table frames
 
 pub const MMIO: &[(usize, usize)] = &[
     (0x0010_0000, 0x00_2000), // VIRT_TEST/RTC  in virt machine
     (0x0200bff8, PAGE_SIZE), // mtime/mtimecmp
+    (0x0c00_0000, 0x40_0000), // PLIC
+    (0x1000_1000, PAGE_SIZE), // virtio-blk (swap device)
 ];
 
 extern "C" {
@@ -41,40 +60,63 @@ extern "C" {
 pub struct MemorySet {
     page_table: PageTable,
     areas: Vec<MapArea>,
+    // program break: the heap area grows from heap_bottom to brk
+    heap_bottom: usize,
+    brk: usize,
+    asid: Asid,
 }
 
 
 impl MemorySet {
-    pub fn new_bare() -> Self {
-        Self {
-            page_table: PageTable::new(),
+    pub fn new_bare() -> Option<Self> {
+        Some(Self {
+            page_table: PageTable::new()?,
             areas: Vec::new(),
-        }
+            heap_bottom: 0,
+            brk: 0,
+            asid: Asid::new(),
+        })
     }
 
-    pub fn new_from_exist(obj: &Self) -> Self {
-        let mut memory_set = Self::new_bare();
-        memory_set.map_trampoline();
-        for area in obj.areas.iter() {
-            memory_set.push(MapArea::new_from_exist(area), None);
+    pub fn new_from_exist(obj: &mut Self) -> Option<Self> {
+        let mut memory_set = Self::new_bare()?;
+        memory_set.map_trampoline()?;
+        memory_set.heap_bottom = obj.heap_bottom;
+        memory_set.brk = obj.brk;
+        for area in obj.areas.iter_mut() {
+            if area.get_perm().contains(MapPermission::U) { // user pages: copy on write, shared memory stays shared
+                let map_area = MapArea::share_from(area, &mut memory_set.page_table, &mut obj.page_table)?;
+                memory_set.areas.push(map_area);
+                continue;
+            }
+            memory_set.push(MapArea::new_from_exist(area), None)?; // trap context is written by kernel directly
             let mut vpn = area.get_beg_vpn();
             let mut vpn_end = area.get_end_vpn();
             while vpn != vpn_end {
-                let src_ppn = obj.translate(vpn).unwrap().ppn();
+                let src_ppn = obj.page_table.translate(vpn).unwrap().ppn();
                 let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
                 dst_ppn.get_bytes_array().copy_from_slice(src_ppn.get_bytes_array());
                 vpn.next();
             }
         }
-        memory_set
+        obj.flush_tlb(); // pages of obj became read-only
+        Some(memory_set)
     }
 
-    fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) {
-        map_area.map(&mut self.page_table);
+    fn push(&mut self, mut map_area: MapArea, data: Option<&'static [u8]>) -> Option<()> {
+        if map_area.map(&mut self.page_table).is_none() {
+            map_area.unmap(&mut self.page_table);
+            return None;
+        }
         if let Some(data) = data {
-            map_area.copy_data(&mut self.page_table, data);
+            if map_area.is_lazy() {
+                map_area.add_file(map_area.get_beg_vpn().into(), data);
+            } else {
+                map_area.copy_data(&mut self.page_table, data);
+            }
         }
         self.areas.push(map_area);
+        Some(())
     }
 
     pub fn recycle(&mut self) {
@@ -82,8 +124,8 @@ impl MemorySet {
         self.areas.clear();
     }
 
-    pub fn insert_framed_area(&mut self, start_va: VirtAddr, end_va: VirtAddr, permission: MapPermission) {
-        self.push(MapArea::new(start_va, end_va, MapType::Framed, permission), None);
+    pub fn insert_framed_area(&mut self, start_va: VirtAddr, end_va: VirtAddr, permission: MapPermission) -> Option<()> {
+        self.push(MapArea::new(start_va, end_va, MapType::Framed, permission), None)
     }
 
     pub fn remove_framed_area(&mut self, start_vpn: VirtPageNum) {
@@ -91,60 +133,315 @@ impl MemorySet {
             .find(|(_, area)| area.get_beg_vpn() == start_vpn) {
             area.unmap(&mut self.page_table);
             self.areas.remove(idx);
+            self.flush_tlb();
+        }
+    }
+
+    pub fn handle_page_fault(&mut self, vpn: VirtPageNum, access: MapPermission) -> FaultResult {
+        if frame_free_num() < SWAP_RESERVE_FRAMES { // this memory set is busy, so swap_out cannot reach it later
+            swap_out(Some(self));
+        }
+        if self.areas.iter().all(|area| !area.contains(vpn)) {
+            match self.grow_stack(vpn) {
+                FaultResult::Handled => {}
+                result => return result,
+            }
+        }
+        if let Some(area) = self.areas.iter_mut().find(|area| area.contains(vpn)) {
+            let result = area.handle_page_fault(&mut self.page_table, vpn, access);
+            self.flush_tlb(); // the page may be remapped by COW
+            result
+        } else {
+            FaultResult::Invalid
+        }
+    }
+
+    // extend the user stack down to vpn if the fault is right below it
+    fn grow_stack(&mut self, vpn: VirtPageNum) -> FaultResult {
+        let stack_top = VirtAddr::from(USER_STACK_TOP).floor();
+        let stack_limit = VirtAddr::from(USER_STACK_TOP - USER_STACK_MAX_SIZE).floor();
+        let stack = match self.areas.iter_mut().find(|area| area.get_end_vpn() == stack_top) {
+            Some(stack) => stack,
+            None => return FaultResult::Invalid,
+        };
+        if vpn >= stack.get_beg_vpn() || vpn.0 + STACK_GROW_GAP < stack.get_beg_vpn().0 {
+            return FaultResult::Invalid;
+        }
+        if vpn < stack_limit || vpn < VirtAddr::from(self.brk).ceil() {
+            return FaultResult::StackOverflow; // or it would run into the heap
+        }
+        stack.set_beg(vpn);
+        FaultResult::Handled
+    }
+
+    // kernel accesses user buffers through physical addresses, so pages must be present (and
+    // not shared by COW when writing) beforehand, return false if some page cannot be accessed
+    pub fn prepare_access(&mut self, start: usize, len: usize, access: MapPermission) -> bool {
+        if !Self::in_user_space(start, len) {
+            return false;
+        }
+        let mut vpn = VirtAddr::from(start).floor();
+        let vpn_end = VirtAddr::from(start + len).ceil();
+        while vpn < vpn_end {
+            if self.translate(vpn).map_or(true, |pte| access == MapPermission::W && !pte.writable()) &&
+                self.handle_page_fault(vpn, access) != FaultResult::Handled {
+                return false;
+            }
+            let pte = self.translate(vpn).unwrap();
+            if !pte.flags().contains(PTEFlags::U) || (access == MapPermission::R && !pte.readable()) {
+                return false; // e.g. the trap context
+            }
+            if access == MapPermission::W {
+                if let Some(area) = self.areas.iter_mut().find(|area| area.contains(vpn)) {
+                    area.mark_dirty(vpn);
+                }
+            }
+            vpn.next();
+        }
+        true
+    }
+
+    // a range given by user space, VirtAddr would drop the high bits beyond the end
+    pub fn in_user_space(start: usize, len: usize) -> bool {
+        start.checked_add(len).map_or(false, |end| end <= USER_SPACE_END)
+    }
+
+    // move the program break, return the new one (or the old one if it cannot be moved)
+    pub fn set_brk(&mut self, new_brk: usize) -> usize {
+        if new_brk < self.heap_bottom || new_brk > HEAP_TOP {
+            return self.brk;
+        }
+        let heap_beg = VirtAddr::from(self.heap_bottom).floor();
+        let heap_end = VirtAddr::from(new_brk).ceil();
+        if self.areas.iter().any(|area| area.get_beg_vpn() > heap_beg && area.get_beg_vpn() < heap_end) {
+            return self.brk; // collide with other areas
+        }
+        if let Some(area) = self.areas.iter_mut().find(|area| area.get_beg_vpn() == heap_beg) {
+            area.set_end(&mut self.page_table, heap_end);
+            self.flush_tlb();
+            self.brk = new_brk;
+        }
+        self.brk
+    }
+
+    // map an anonymous area at start, or at an address picked by kernel if start is 0
+    pub fn mmap(&mut self, start: usize, len: usize, permission: MapPermission) -> Option<usize> {
+        if !Self::in_user_space(start, len) {
+            return None;
+        }
+        let page_num = (len + PAGE_SIZE - 1) / PAGE_SIZE;
+        let beg = self.pick_mmap_area(start, page_num)?;
+        let end = VirtPageNum(beg.0 + page_num);
+        self.push(MapArea::new(beg.into(), end.into(), MapType::Framed, permission), None)?;
+        self.merge_mmap_areas();
+        Some(VirtAddr::from(beg).into())
+    }
+
+    // attach frames of a shared memory segment at start, or at an address picked by kernel if start is 0
+    pub fn attach_shm(&mut self, start: usize, frames: Vec<FrameTracker>, permission: MapPermission) -> Option<usize> {
+        let beg = self.pick_mmap_area(start, frames.len())?;
+        let end = VirtPageNum(beg.0 + frames.len());
+        let mut map_area = MapArea::new(beg.into(), end.into(), MapType::Shared, permission);
+        map_area.set_shared_frames(frames);
+        self.push(map_area, None)?;
+        Some(VirtAddr::from(beg).into())
+    }
+
+    pub fn detach_shm(&mut self, start: usize) -> bool {
+        let vpn = VirtAddr::from(start).floor();
+        if let Some(idx) = self.areas.iter().position(|area| area.is_shared() && area.get_beg_vpn() == vpn) {
+            self.areas[idx].unmap(&mut self.page_table);
+            self.areas.remove(idx); // drop the references of the frames
+            self.flush_tlb();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn pick_mmap_area(&self, start: usize, page_num: usize) -> Option<VirtPageNum> {
+        let beg = if start == 0 {
+            self.find_free_area(page_num)?
+        } else {
+            VirtAddr::from(start).floor()
+        };
+        let end = VirtPageNum(beg.0 + page_num);
+        if !Self::in_mmap_region(beg, end) ||
+            self.areas.iter().any(|area| area.get_beg_vpn() < end && beg < area.get_end_vpn()) {
+            return None;
+        }
+        Some(beg)
+    }
+
+    pub fn munmap(&mut self, start: usize, len: usize) -> bool {
+        if !Self::in_user_space(start, len) {
+            return false;
+        }
+        let beg = VirtAddr::from(start).floor();
+        let end = VirtAddr::from(start + len).ceil();
+        if !Self::in_mmap_region(beg, end) || self.overlap_shm(beg, end) {
+            return false;
+        }
+        self.split_areas(beg, end);
+        let (removed, areas): (Vec<MapArea>, Vec<MapArea>) = core::mem::take(&mut self.areas)
+            .into_iter()
+            .partition(|area| beg <= area.get_beg_vpn() && area.get_end_vpn() <= end);
+        self.areas = areas;
+        for mut area in removed {
+            area.unmap(&mut self.page_table);
+        }
+        self.flush_tlb();
+        true
+    }
+
+    pub fn mprotect(&mut self, start: usize, len: usize, permission: MapPermission) -> bool {
+        if !Self::in_user_space(start, len) {
+            return false;
+        }
+        let beg = VirtAddr::from(start).floor();
+        let end = VirtAddr::from(start + len).ceil();
+        if !Self::in_mmap_region(beg, end) || self.overlap_shm(beg, end) {
+            return false;
+        }
+        let mapped: usize = self.areas.iter()
+            .filter(|area| area.get_beg_vpn() < end && beg < area.get_end_vpn())
+            .map(|area| area.get_end_vpn().0.min(end.0) - area.get_beg_vpn().0.max(beg.0))
+            .sum();
+        if mapped != end.0 - beg.0 {
+            return false; // the whole range must be mapped
+        }
+        // the range is checked as a whole above, so no area is changed if it fails
+        self.split_areas(beg, end);
+        for area in self.areas.iter_mut() {
+            if beg <= area.get_beg_vpn() && area.get_end_vpn() <= end {
+                area.set_perm(&mut self.page_table, permission);
+            }
         }
+        self.merge_mmap_areas();
+        self.flush_tlb();
+        true
+    }
+
+    fn in_mmap_region(beg: VirtPageNum, end: VirtPageNum) -> bool {
+        VirtAddr::from(MMAP_BASE).floor() <= beg && beg < end && end <= VirtAddr::from(MMAP_TOP).floor()
+    }
+
+    // shared memory segments are only detached as a whole
+    fn overlap_shm(&self, beg: VirtPageNum, end: VirtPageNum) -> bool {
+        self.areas.iter().any(|area| area.is_shared() && area.get_beg_vpn() < end && beg < area.get_end_vpn())
+    }
+
+    fn find_free_area(&self, page_num: usize) -> Option<VirtPageNum> {
+        let mut beg = VirtAddr::from(MMAP_BASE).floor();
+        loop {
+            let end = VirtPageNum(beg.0 + page_num);
+            if !Self::in_mmap_region(beg, end) {
+                return None;
+            }
+            match self.areas.iter()
+                .filter(|area| area.get_beg_vpn() < end && beg < area.get_end_vpn())
+                .map(|area| area.get_end_vpn())
+                .max() {
+                Some(area_end) => beg = area_end, // skip the overlapped areas
+                None => return Some(beg),
+            }
+        }
+    }
+
+    // split areas at the boundaries of [beg, end), so each area is either inside or outside of it
+    fn split_areas(&mut self, beg: VirtPageNum, end: VirtPageNum) {
+        let mut areas = Vec::new();
+        for mut area in core::mem::take(&mut self.areas) {
+            for vpn in [beg, end] {
+                if area.get_beg_vpn() < vpn && vpn < area.get_end_vpn() {
+                    let tail = area.split_off(vpn);
+                    areas.push(area);
+                    area = tail;
+                }
+            }
+            areas.push(area);
+        }
+        self.areas = areas;
+    }
+
+    fn merge_mmap_areas(&mut self) {
+        let mmap_beg = VirtAddr::from(MMAP_BASE).floor();
+        let mmap_end = VirtAddr::from(MMAP_TOP).floor();
+        self.areas.sort_by_key(|area| area.get_beg_vpn());
+        let mut areas: Vec<MapArea> = Vec::new();
+        for area in core::mem::take(&mut self.areas) {
+            if let Some(last) = areas.last_mut() {
+                if mmap_beg <= last.get_beg_vpn() && area.get_end_vpn() <= mmap_end && last.can_merge(&area) {
+                    last.merge(area);
+                    continue;
+                }
+            }
+            areas.push(area);
+        }
+        self.areas = areas;
+    }
+
+    // clock scan from vpn, return the page swapped out
+    pub fn swap_out(&mut self, vpn: VirtPageNum) -> Option<VirtPageNum> {
+        self.areas.sort_by_key(|area| area.get_beg_vpn());
+        let victim = self.areas.iter_mut()
+            .filter(|area| area.get_end_vpn() > vpn)
+            .find_map(|area| area.swap_out(&mut self.page_table, vpn));
+        self.flush_tlb(); // accessed bits are cleared as well
+        victim
     }
 
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
         self.page_table.translate(vpn)
     }
 
-    pub fn new_kernel() -> Self {
-        let mut memory_set = Self::new_bare();
+    pub fn new_kernel() -> Option<Self> {
+        let mut memory_set = Self::new_bare()?;
         // map trampoline
-        memory_set.map_trampoline();
+        memory_set.map_trampoline()?;
         // map kernel sections
         memory_set.push(MapArea::new(
             (stext as usize).into(),
             (etext as usize).into(),
             MapType::Identical,
             MapPermission::R | MapPermission::X,
-        ), None);
+        ), None)?;
         memory_set.push(MapArea::new(
             (srodata as usize).into(),
             (erodata as usize).into(),
             MapType::Identical,
             MapPermission::R,
-        ), None);
+        ), None)?;
         memory_set.push(MapArea::new(
             (sdata as usize).into(),
             (edata as usize).into(),
             MapType::Identical,
             MapPermission::R | MapPermission::W,
-        ), None);
+        ), None)?;
         memory_set.push(MapArea::new(
             (sbss_with_stack as usize).into(),
             (ebss as usize).into(),
             MapType::Identical,
             MapPermission::R | MapPermission::W,
-        ), None);
+        ), None)?;
         memory_set.push(MapArea::new(
             (ekernel as usize).into(),
             MEMORY_END.into(),
             MapType::Identical,
             MapPermission::R | MapPermission::W,
-        ), None);// BUFFER_BEG-MEMORY_END: shared memory between kernel and user
+        ), None)?;// BUFFER_BEG-MEMORY_END: shared memory between kernel and user
         memory_set.push(MapArea::new(
             UART_BASE_ADDRESS.into(),
             (UART_BASE_ADDRESS + 0x6).into(),
             MapType::Identical,
             MapPermission::R | MapPermission::W,
-        ), None);
+        ), None)?;
         memory_set.push(MapArea::new(
             0x0200bff8.into(),
             (0x0200bff8 + PAGE_SIZE).into(),
             MapType::Identical,
             MapPermission::R | MapPermission::W,
-        ), None);
+        ), None)?;
         for pair in MMIO {
             memory_set.push(
                 MapArea::new(
@@ -154,65 +451,193 @@ impl MemorySet {
                     MapPermission::R | MapPermission::W,
                 ),
                 None,
-            );
+            )?;
         }
-        memory_set
+        Some(memory_set)
     }
 
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
-        let mut memory_set = Self::new_bare();
+    // return the memory set, user sp and entry point, or why the image is rejected
+    pub fn from_elf(elf_data: &'static [u8]) -> Result<(Self, usize, usize), ElfError> {
+        let elf = xmas_elf::ElfFile::new(elf_data).map_err(|_| ElfError::Malformed)?;
+        let base = check_header(&elf)?;
+        let entry_point = base.wrapping_add(elf.header.pt2.entry_point() as usize);
+        let mut memory_set = Self::new_bare().ok_or(ElfError::OutOfMemory)?;
         // map trampoline
-        memory_set.map_trampoline();
+        memory_set.map_trampoline().ok_or(ElfError::OutOfMemory)?;
         // map program headers of elf, with U flag
-        let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
-        let elf_header = elf.header;
-        let magic = elf_header.pt1.magic;
-        assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
-        let ph_count = elf_header.pt2.ph_count();
-        let mut max_end_vpn = VirtPageNum(0);
-        for i in 0..ph_count {
-            let ph = elf.program_header(i).unwrap();
-            if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
-                let start_va: VirtAddr = (ph.virtual_addr() as usize).into();
-                let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize).into();
-                let mut map_perm = MapPermission::U;
-                let ph_flags = ph.flags();
-                if ph_flags.is_read() { map_perm |= MapPermission::R; }
-                if ph_flags.is_write() { map_perm |= MapPermission::W; }
-                if ph_flags.is_execute() { map_perm |= MapPermission::X; }
-                let map_area = MapArea::new(
-                    start_va,
-                    end_va,
-                    MapType::Framed,
-                    map_perm,
-                );
-                max_end_vpn = map_area.get_end_vpn();
-                memory_set.push(
-                    map_area,
-                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                );
+        let mut max_end_va = 0;
+        let mut segments: Vec<MapArea> = Vec::new();
+        let mut entry_valid = false;
+        let mut stack_perm = MapPermission::R | MapPermission::W | MapPermission::U;
+        for ph in elf.program_iter() {
+            match ph.get_type().map_err(|_| ElfError::Malformed)? {
+                xmas_elf::program::Type::Load => {}
+                xmas_elf::program::Type::Interp => return Err(ElfError::BadSegment), // no dynamic linker
+                xmas_elf::program::Type::OsSpecific(PT_GNU_STACK) => {
+                    if ph.flags().is_execute() { stack_perm |= MapPermission::X; }
+                    continue;
+                }
+                _ => continue,
+            }
+            let (mem_size, file_size, offset) = (ph.mem_size() as usize, ph.file_size() as usize, ph.offset() as usize);
+            if mem_size == 0 {
+                continue;
+            }
+            if file_size > mem_size || offset.checked_add(file_size).map_or(true, |end| end > elf_data.len()) {
+                return Err(ElfError::Malformed);
+            }
+            let start_va = base.checked_add(ph.virtual_addr() as usize).ok_or(ElfError::BadSegment)?;
+            let end_va = start_va.checked_add(mem_size).ok_or(ElfError::BadSegment)?;
+            let (start_vpn, end_vpn) = (VirtAddr::from(start_va).floor(), VirtAddr::from(end_va).ceil());
+            // segments come sorted by address and may share a page, but not bytes
+            if start_va < PAGE_SIZE || end_va > USER_STACK_TOP - USER_STACK_MAX_SIZE || start_va < max_end_va {
+                return Err(ElfError::BadSegment);
+            }
+            let mut map_perm = MapPermission::U;
+            let ph_flags = ph.flags();
+            if ph_flags.is_read() { map_perm |= MapPermission::R; }
+            if ph_flags.is_write() { map_perm |= MapPermission::R | MapPermission::W; } // W alone is reserved
+            if ph_flags.is_execute() { map_perm |= MapPermission::X; }
+            if ph_flags.is_execute() && start_va <= entry_point && entry_point < end_va {
+                entry_valid = true;
+            }
+            let data = &elf_data[offset..offset + file_size];
+            // bytes beyond the file size, BSS included, stay zero in the fresh frames
+            match segments.last_mut() {
+                Some(last) if last.get_end_vpn() > start_vpn => {
+                    // the first page is shared with the last segment, it gets an area of its own
+                    // with both permissions and both file contents
+                    if last.get_beg_vpn() < start_vpn {
+                        let shared = last.split_off(start_vpn);
+                        segments.push(shared);
+                    }
+                    let shared = segments.last_mut().unwrap();
+                    let perm = shared.get_perm() | map_perm;
+                    shared.set_perm(&mut memory_set.page_table, perm);
+                    shared.add_file(start_va.into(), data);
+                    if end_vpn.0 > start_vpn.0 + 1 {
+                        let mut map_area = MapArea::new(start_va.into(), end_va.into(), MapType::Framed, map_perm);
+                        map_area.add_file(start_va.into(), data);
+                        segments.push(map_area.split_off(VirtPageNum(start_vpn.0 + 1)));
+                    }
+                }
+                _ => {
+                    let mut map_area = MapArea::new(start_va.into(), end_va.into(), MapType::Framed, map_perm);
+                    map_area.add_file(start_va.into(), data);
+                    segments.push(map_area);
+                }
+            }
+            max_end_va = end_va;
+        }
+        for map_area in segments {
+            memory_set.push(map_area, None).ok_or(ElfError::OutOfMemory)?;
+        }
+        if !entry_valid {
+            return Err(ElfError::BadEntry);
+        }
+        if base != 0 {
+            for (offset, addend) in relocations(&elf)? {
+                memory_set.write_word(base.wrapping_add(offset), base.wrapping_add(addend))?;
             }
         }
-        // map user stack with U flags
-        let max_end_va: VirtAddr = max_end_vpn.into();
-        let mut user_stack_bottom: usize = max_end_va.into();
-        // guard page
-        user_stack_bottom += PAGE_SIZE;
-        let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
+        // map heap with U flags, which is empty until the program break moves
+        let heap_bottom: usize = VirtAddr::from(VirtAddr::from(max_end_va).ceil()).into();
+        memory_set.push(MapArea::new(
+            heap_bottom.into(),
+            heap_bottom.into(),
+            MapType::Framed,
+            MapPermission::R | MapPermission::W | MapPermission::U,
+        ), None).ok_or(ElfError::OutOfMemory)?;
+        memory_set.heap_bottom = heap_bottom;
+        memory_set.brk = heap_bottom;
+        // map user stack with U flags, it grows down on page faults until USER_STACK_MAX_SIZE
+        let user_stack_top = USER_STACK_TOP;
+        let user_stack_bottom = user_stack_top - USER_STACK_SIZE;
         memory_set.push(MapArea::new(
             user_stack_bottom.into(),
             user_stack_top.into(),
             MapType::Framed,
-            MapPermission::R | MapPermission::W | MapPermission::U,
-        ), None);
+            stack_perm,
+        ), None).ok_or(ElfError::OutOfMemory)?;
         //map TrapContext
         memory_set.push(MapArea::new(
             TRAP_CONTEXT.into(),
             TRAMPOLINE.into(),
             MapType::Framed,
             MapPermission::R | MapPermission::W,
-        ), None);
-        (memory_set, user_stack_top, elf.header.pt2.entry_point() as usize)
+        ), None).ok_or(ElfError::OutOfMemory)?;
+        Ok((memory_set, user_stack_top, entry_point))
+    }
+
+    // (start, end, permission, contents) of the user areas for a core dump, pages are not faulted in
+    // areas beyond the limit of total size come without contents
+    pub fn dump_areas(&self, limit: usize) -> Vec<(usize, usize, MapPermission, Vec<u8>)> {
+        let mut areas = Vec::new();
+        let mut size = 0;
+        for area in self.areas.iter().filter(|area| area.get_perm().contains(MapPermission::U)) {
+            let (beg, end) = (area.get_beg_vpn(), area.get_end_vpn());
+            let len = (end.0 - beg.0) * PAGE_SIZE;
+            let mut data = Vec::new();
+            // left out as well if the heap cannot hold it, a fault must not panic the kernel
+            if size + len <= limit && data.try_reserve_exact(len).is_ok() {
+                data.resize(len, 0);
+                for (i, page) in data.chunks_exact_mut(PAGE_SIZE).enumerate() {
+                    area.read_page(VirtPageNum(beg.0 + i), page);
+                }
+                size += len;
+            }
+            areas.push((VirtAddr::from(beg).into(), VirtAddr::from(end).into(), area.get_perm(), data));
+        }
+        areas
+    }
+
+    // write a word to a user page regardless of its permission, e.g. to apply relocations
+    fn write_word(&mut self, va: usize, value: usize) -> Result<(), ElfError> {
+        if va % core::mem::size_of::<usize>() != 0 {
+            return Err(ElfError::BadRelocation);
+        }
+        let vpn = VirtAddr::from(va).floor();
+        let area = self.areas.iter_mut()
+            .find(|area| area.contains(vpn) && area.is_lazy())
+            .ok_or(ElfError::BadRelocation)?;
+        if self.page_table.translate(vpn).is_none() {
+            match area.handle_page_fault(&mut self.page_table, vpn, area.get_perm()) {
+                FaultResult::Handled => {}
+                _ => return Err(ElfError::OutOfMemory),
+            }
+        }
+        area.mark_dirty(vpn);
+        let ppn = self.page_table.translate(vpn).unwrap().ppn();
+        let offset = VirtAddr::from(va).page_offset();
+        ppn.get_bytes_array()[offset..offset + 8].copy_from_slice(&value.to_ne_bytes());
+        Ok(())
+    }
+
+    // push argc, argv, envp and auxv to the user stack in the System V layout, return the new sp
+    pub fn init_stack(&mut self, user_sp: usize, args: &[String], envs: &[String]) -> Option<usize> {
+        let strs_len: usize = args.iter().chain(envs).map(|s| s.len() + 1).sum();
+        let word_num = 1 + (args.len() + 1) + (envs.len() + 1) + 4;
+        let strs_beg = user_sp - strs_len;
+        let sp = (strs_beg - word_num * 8) & !0xf;
+        let mut words = Vec::with_capacity(word_num);
+        let mut image = alloc::vec![0u8; user_sp - sp];
+        let mut str_va = strs_beg;
+        words.push(args.len());
+        for strs in [args, envs] {
+            for s in strs {
+                let offset = str_va - sp;
+                image[offset..offset + s.len()].copy_from_slice(s.as_bytes());
+                words.push(str_va);
+                str_va += s.len() + 1;
+            }
+            words.push(0);
+        }
+        words.extend_from_slice(&[AT_PAGESZ, PAGE_SIZE, AT_NULL, 0]);
+        for (i, word) in words.iter().enumerate() {
+            image[i * 8..i * 8 + 8].copy_from_slice(&word.to_ne_bytes());
+        }
+        UserSlice::new(sp as *const u8, image.len()).copy_from(self, &image).ok()?;
+        Some(sp)
     }
 
     pub fn activate(&mut self) {
@@ -226,24 +651,40 @@ impl MemorySet {
         self.page_table.token()
     }
 
-    fn map_trampoline(&mut self) {
+    // satp to run the memory set with, an ASID is assigned first if it has none of this generation
+    pub fn user_satp(&mut self) -> usize {
+        ASID_MANAGER.borrow_exclusive().refresh(&mut self.asid);
+        self.page_table.token() | self.asid.get() << ASID_SHIFT
+    }
+
+    // flush the TLB entries of this memory set only
+    pub fn flush_tlb(&self) {
+        flush_asid(self.asid.get());
+    }
+
+    fn map_trampoline(&mut self) -> Option<()> {
         self.page_table.map(
             VirtAddr::from(TRAMPOLINE).into(),
             PhysAddr::from(strampoline as usize).into(),
             PTEFlags::R | PTEFlags::X,
-        );
+        )
     }
 
-    pub fn map_buffer_user(&mut self, pid: usize) {
+    pub fn map_buffer_user(&mut self, pid: usize) -> Option<()> {
         let vpn = VirtAddr::from(BUFFER).into();
         let ppn = PhysAddr::from(BUFFER_BEG + pid * PAGE_SIZE).into();
-        self.page_table.map(vpn, ppn, PTEFlags::U | PTEFlags::R | PTEFlags::W);
+        self.page_table.map(vpn, ppn, PTEFlags::U | PTEFlags::R | PTEFlags::W)
+    }
+
+    // frames held by the memory set, used to choose the victim when memory runs out
+    pub fn frame_num(&self) -> usize {
+        self.areas.iter().map(|area| area.frame_num()).sum::<usize>() + self.page_table.frame_num()
     }
 }
 
 lazy_static! {
     pub static ref KERNEL_SPACE: Arc<SafeCellSingle<MemorySet>> = Arc::new( unsafe {
-        SafeCellSingle::new(MemorySet::new_kernel()
+        SafeCellSingle::new(MemorySet::new_kernel().unwrap()
     )});
 }
 
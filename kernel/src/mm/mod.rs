@@ -1,32 +1,61 @@
-use crate::mm::buddy::{Allocator, AllocatorWrap, KERNEL_HEAP_SIZE};
+use core::alloc::Layout;
+
+use crate::mm::asid::init_asid;
+use crate::mm::buddy::{AllocatorWrap, KERNEL_HEAP_SIZE};
+use crate::mm::heap::Heap;
 use crate::mm::memory_set::{KERNEL_SPACE, remap_test};
+use crate::mm::slab::SlabAllocator;
+use crate::mm::swap::init_swap;
 
+pub mod asid;
 pub mod buddy;
+pub mod elf;
+pub mod heap;
+pub mod slab;
 pub mod address;
 pub mod page_table;
 pub mod frame_allocator;
 pub mod memory_set;
 pub mod area;
+pub mod shm;
+pub mod swap;
+pub mod user_ptr;
 
 
 pub fn init_heap() {
     unsafe {
-        INNER_ALLOCATOR.init(HEAP_SPACE.as_ptr() as usize);
-        HEAP_ALLOCATOR.allocator = &mut INNER_ALLOCATOR as *mut Allocator as usize;
+        INNER_ALLOCATOR.init(HEAP_SPACE.0.as_ptr() as usize, KERNEL_HEAP_SIZE);
+        HEAP_ALLOCATOR.allocator = &mut INNER_ALLOCATOR as *mut Heap as usize;
+        HEAP_ALLOCATOR.slab = &mut SLAB_ALLOCATOR as *mut SlabAllocator as usize;
     }
 }
 
-static mut HEAP_SPACE: [u8; KERNEL_HEAP_SIZE] = [0; KERNEL_HEAP_SIZE];
+// blocks (and slabs) are aligned to their sizes only if the heap begins at a page
+#[repr(align(4096))]
+struct HeapSpace([u8; KERNEL_HEAP_SIZE]);
+
+static mut HEAP_SPACE: HeapSpace = HeapSpace([0; KERNEL_HEAP_SIZE]);
+
+static mut INNER_ALLOCATOR: Heap = Heap::empty();
 
-static mut INNER_ALLOCATOR: Allocator = Allocator::empty();
+static mut SLAB_ALLOCATOR: SlabAllocator = SlabAllocator::empty();
 
 #[global_allocator]
 static mut HEAP_ALLOCATOR: AllocatorWrap = AllocatorWrap::empty();
 
+#[alloc_error_handler]
+fn alloc_error(layout: Layout) -> ! {
+    println!("[kernel] Heap allocation failed: size = {}, align = {}", layout.size(), layout.align());
+    unsafe { INNER_ALLOCATOR.print_stats(); }
+    panic!("[kernel] Out of kernel heap!");
+}
+
 
 pub fn init_mm() {
     init_heap();
     frame_allocator::init_frame_allocator();
     KERNEL_SPACE.borrow_exclusive().activate();
+    init_asid();
     remap_test();
+    init_swap();
 }
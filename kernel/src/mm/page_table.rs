@@ -7,6 +7,8 @@ use crate::mm::address::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use crate::mm::frame_allocator::{frame_alloc, FrameTracker};
 
 const VPN_PTE_BITS: usize = 9;
+// a leaf at level 1 maps a 2 MiB megapage, at level 2 a 1 GiB gigapage
+pub const PAGE_LEVELS: usize = 3;
 
 bitflags! {
     pub struct PTEFlags: u8 {
@@ -67,40 +69,58 @@ impl PageTableEntry {
         (self.flags() & PTEFlags::X).bits != 0
     }
 
+    pub fn accessed(&self) -> bool {
+        (self.flags() & PTEFlags::A).bits != 0
+    }
+
+    pub fn dirty(&self) -> bool {
+        (self.flags() & PTEFlags::D).bits != 0
+    }
+
+    pub fn clear_accessed(&mut self) {
+        self.bits &= !(PTEFlags::A.bits as usize);
+    }
+
     pub fn is_leaf(&self) -> bool {
         self.readable() || self.writable() || self.executable()
     }
 }
 
 impl PageTable {
-    pub fn new() -> Self {
-        let frame = frame_alloc().unwrap();
+    pub fn new() -> Option<Self> {
+        let frame = frame_alloc()?;
         PageTable::empty_init(frame.ppn);
-        PageTable {
+        Some(PageTable {
             root_ppn: frame.ppn,
             frames: vec![frame],
-        }
+        })
     }
 
-    pub fn new_tmp(satp: usize) -> Self { // temp page_table (find mem by software method)
-        Self {
-            root_ppn: PhysPageNum::from(satp),
-            frames: Vec::new(),
-        }
+    // return None if frames for the page table run out
+    pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> Option<()> {
+        self.map_huge(vpn, ppn, flags, 0)
     }
 
-    pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
-        let pte = self.create_pte(vpn).unwrap();
+    // map a page of the level, vpn and ppn must be aligned to it
+    pub fn map_huge(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags, level: usize) -> Option<()> {
+        assert!(vpn.0 % page_num(level) == 0 && ppn.0 % page_num(level) == 0);
+        let pte = self.create_pte(vpn, level)?;
         *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        Some(())
     }
 
+    // a huge page is unmapped as a whole
     pub fn unmap(&mut self, vpn: VirtPageNum) {
         let pte = self.find_pte(vpn).unwrap();
         *pte = PageTableEntry::empty();
     }
 
-
     pub fn find_pte(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+        self.find_leaf(vpn).map(|(pte, _)| pte)
+    }
+
+    // the leaf entry covering vpn and its level
+    pub fn find_leaf(&self, vpn: VirtPageNum) -> Option<(&mut PageTableEntry, usize)> {
         let mut index = [0usize; 3];
         for i in 0..3 {
             index[i] = (vpn.0 >> (VPN_PTE_BITS * (2 - i))) & ((1 << VPN_PTE_BITS) - 1);
@@ -111,24 +131,18 @@ impl PageTable {
             if !pte.is_valid() {
                 return None;
             }
+            if pte.is_leaf() {
+                return Some((pte, 2 - i));
+            }
             if i == 2 {
-                return if pte.is_leaf() {
-                    Some(pte)
-                } else {
-                    None
-                }; // leaf page is (in)valid
-            } else {
-                if pte.is_leaf() {
-                    return None; // TODO: huge page
-                } else {
-                    ppn = pte.ppn();
-                }
+                return None; // leaf page is invalid
             }
+            ppn = pte.ppn();
         }
         return None;
     }
 
-    fn create_pte(&mut self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+    fn create_pte(&mut self, vpn: VirtPageNum, level: usize) -> Option<&mut PageTableEntry> {
         let mut index = [0usize; 3];
         for i in 0..3 {
             index[i] = (vpn.0 >> (VPN_PTE_BITS * (2 - i))) & ((1 << VPN_PTE_BITS) - 1);
@@ -136,16 +150,25 @@ impl PageTable {
         let mut ppn = self.root_ppn;
         for i in 0..3 {
             let pte = &mut ppn.get_pte_array()[index[i]];
-            if i == 2 {
+            if i == 2 - level {
                 return Some(pte);
             } else {
                 if !pte.is_valid() {
-                    let frame = frame_alloc().unwrap();
+                    let frame = frame_alloc()?;
                     PageTable::empty_init(frame.ppn);
                     *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
                     self.frames.push(frame);
                 } else if pte.is_leaf() {
-                    return None; // TODO: huge page
+                    // split the huge page into pages of the next level with the same flags,
+                    // the caller flushes the TLB
+                    let frame = frame_alloc()?;
+                    let (huge_ppn, flags) = (pte.ppn(), pte.flags());
+                    let step = page_num(1 - i);
+                    for (j, child) in frame.ppn.get_pte_array().iter_mut().enumerate() {
+                        *child = PageTableEntry::new((huge_ppn.0 + j * step).into(), flags);
+                    }
+                    *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
+                    self.frames.push(frame);
                 }
                 ppn = pte.ppn();
             }
@@ -157,17 +180,25 @@ impl PageTable {
         8usize << 60 | (self.root_ppn.0)
     }
 
+    // the entry of a huge page is narrowed to the 4 KiB page of vpn
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
-        self.find_pte(vpn).map(|pte| *pte)
+        self.find_leaf(vpn).map(|(pte, level)| {
+            let offset = vpn.0 & (page_num(level) - 1);
+            PageTableEntry::new((pte.ppn().0 + offset).into(), pte.flags())
+        })
     }
 
     pub fn translate_va(&self, va: VirtAddr) -> Option<PhysAddr> {
-        self.find_pte(va.floor()).map(|pte| {
+        self.translate(va.floor()).map(|pte| {
             let pa_beg: PhysAddr = pte.ppn().into();
             (pa_beg.0 + va.page_offset()).into()
         })
     }
 
+    pub fn frame_num(&self) -> usize {
+        self.frames.len()
+    }
+
     pub fn recycle(&mut self) {
         self.frames.clear();
     }
@@ -180,29 +211,7 @@ impl PageTable {
     }
 }
 
-
-pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
-    let tmp_page_table = PageTable::new_tmp(token);
-    let mut va = VirtAddr::from(ptr as usize);
-    let end_va = VirtAddr::from(ptr as usize + len);
-    let mut vec = Vec::new();
-    while va < end_va {
-        let mut vpn = va.floor();
-        let ppn = tmp_page_table.translate(vpn).unwrap().ppn();
-        vpn.next();
-        let va_end_page = VirtAddr::from(vpn).min(end_va);
-        if va_end_page.page_offset() == 0 {
-            vec.push(&mut ppn.get_bytes_array()[va.page_offset()..]);
-        } else {
-            vec.push(&mut ppn.get_bytes_array()[va.page_offset()..va_end_page.page_offset()]);
-        }
-        va = va_end_page;
-    }
-    vec
-}
-
-pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
-    let page_table = PageTable::new_tmp(token);
-    let va = ptr as usize;
-    page_table.translate_va(VirtAddr::from(va)).unwrap().get_mut()
+// number of 4 KiB pages in a page of the level
+pub fn page_num(level: usize) -> usize {
+    1 << (VPN_PTE_BITS * level)
 }
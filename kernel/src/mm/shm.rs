@@ -0,0 +1,68 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use lazy_static::lazy_static;
+
+use crate::mm::frame_allocator::{frame_alloc, frame_total_num, FrameTracker};
+use crate::mm::memory_set::PAGE_SIZE;
+use crate::sync::safe_cell_single::SafeCellSingle;
+use crate::syscall::error::{SysError, SysResult};
+
+pub const IPC_PRIVATE: usize = 0;
+pub const SHMMAX: usize = 0x100_0000; // bytes of a segment, checked before any frame is taken
+
+// a shared memory segment, every attached area holds its own reference of the frames,
+// so the frames are freed when the segment is removed and the last area is detached
+pub struct ShmSegment {
+    key: usize,
+    frames: Vec<FrameTracker>,
+}
+
+pub struct ShmManager {
+    segments: BTreeMap<usize, ShmSegment>,
+    next_id: usize,
+}
+
+impl ShmManager {
+    pub fn new() -> Self {
+        Self {
+            segments: BTreeMap::new(),
+            next_id: 1,
+        }
+    }
+
+    // find the segment of key, or create one if it does not exist, return its id
+    pub fn get(&mut self, key: usize, page_num: usize, create: bool) -> SysResult {
+        if key != IPC_PRIVATE {
+            if let Some((id, segment)) = self.segments.iter().find(|(_, segment)| segment.key == key) {
+                return if segment.frames.len() >= page_num { Ok(*id) } else { Err(SysError::EINVAL) };
+            }
+        }
+        if !create && key != IPC_PRIVATE {
+            return Err(SysError::ENOENT);
+        }
+        if page_num > SHMMAX / PAGE_SIZE || page_num > frame_total_num() {
+            return Err(SysError::EINVAL);
+        }
+        let mut frames = Vec::with_capacity(page_num);
+        for _ in 0..page_num {
+            frames.push(frame_alloc().ok_or(SysError::ENOMEM)?);
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.segments.insert(id, ShmSegment { key, frames });
+        Ok(id)
+    }
+
+    pub fn share_frames(&self, id: usize) -> Option<Vec<FrameTracker>> {
+        self.segments.get(&id).map(|segment| segment.frames.iter().map(|frame| frame.share()).collect())
+    }
+
+    pub fn remove(&mut self, id: usize) -> bool {
+        self.segments.remove(&id).is_some()
+    }
+}
+
+lazy_static! {
+    pub static ref SHM_MANAGER: SafeCellSingle<ShmManager> = unsafe { SafeCellSingle::new(ShmManager::new()) };
+}
@@ -0,0 +1,132 @@
+use core::mem::size_of;
+use core::ptr::null_mut;
+
+use crate::mm::buddy::BLOCK_UNIT_SIZE;
+use crate::mm::heap::Heap;
+
+// size classes are 8, 16, ..., 1024 bytes, larger requests go to the buddy allocator
+pub const SLAB_MIN_SIZE: usize = 8;
+pub const SLAB_MAX_SIZE: usize = 1024;
+const SLAB_CLASS_NUM: usize = 8;
+
+// kept at the beginning of every slab page, objects follow it
+struct SlabHeader {
+    // prev/next slab with free objects (0 for none)
+    prev: usize,
+    next: usize,
+    // head of the free object link
+    free: usize,
+    // objects in use
+    used: usize,
+}
+
+#[derive(Copy, Clone)]
+struct SlabCache {
+    // slabs having free objects
+    partial: usize,
+}
+
+pub struct SlabAllocator {
+    caches: [SlabCache; SLAB_CLASS_NUM],
+}
+
+impl SlabHeader {
+    unsafe fn from_addr<'a>(addr: usize) -> &'a mut Self {
+        &mut *(addr as *mut SlabHeader)
+    }
+}
+
+impl SlabAllocator {
+    pub const fn empty() -> Self {
+        SlabAllocator {
+            caches: [SlabCache { partial: 0 }; SLAB_CLASS_NUM],
+        }
+    }
+
+    // the size class serving the layout, None if it's too large for slabs
+    pub fn class_of(size: usize, align: usize) -> Option<usize> {
+        let size = size.max(align).max(SLAB_MIN_SIZE).next_power_of_two();
+        if size > SLAB_MAX_SIZE {
+            return None;
+        }
+        Some((size / SLAB_MIN_SIZE).trailing_zeros() as usize)
+    }
+
+    pub unsafe fn alloc(&mut self, class: usize, heap: &mut Heap) -> *mut u8 {
+        if self.caches[class].partial == 0 {
+            let page = heap.alloc(0);
+            if page.is_null() {
+                return null_mut();
+            }
+            self.new_slab(page as usize, class);
+        }
+        let slab = self.caches[class].partial;
+        let header = SlabHeader::from_addr(slab);
+        let obj = header.free;
+        header.free = *(obj as *const usize);
+        header.used += 1;
+        if header.free == 0 { // full now
+            self.unlink(slab, class);
+        }
+        obj as *mut u8
+    }
+
+    pub unsafe fn dealloc(&mut self, ptr: *mut u8, class: usize, heap: &mut Heap) {
+        let slab = self.slab_of(ptr as usize);
+        let header = SlabHeader::from_addr(slab);
+        if header.free == 0 { // it was full
+            self.link(slab, class);
+        }
+        *(ptr as *mut usize) = header.free;
+        header.free = ptr as usize;
+        header.used -= 1;
+        // keep the last slab of the class to avoid refilling it again and again
+        if header.used == 0 && (header.prev != 0 || header.next != 0) {
+            self.unlink(slab, class);
+            heap.dealloc(slab, 0);
+        }
+    }
+
+    // carve a fresh page into objects of the class
+    unsafe fn new_slab(&mut self, page: usize, class: usize) {
+        let size = SLAB_MIN_SIZE << class;
+        let first = size_of::<SlabHeader>().max(size);
+        let header = SlabHeader::from_addr(page);
+        header.free = 0;
+        header.used = 0;
+        for obj in (page + first..page + BLOCK_UNIT_SIZE).step_by(size).rev() {
+            *(obj as *mut usize) = header.free;
+            header.free = obj;
+        }
+        self.link(page, class);
+    }
+
+    unsafe fn link(&mut self, slab: usize, class: usize) {
+        let header = SlabHeader::from_addr(slab);
+        header.prev = 0;
+        header.next = self.caches[class].partial;
+        if header.next != 0 {
+            SlabHeader::from_addr(header.next).prev = slab;
+        }
+        self.caches[class].partial = slab;
+    }
+
+    unsafe fn unlink(&mut self, slab: usize, class: usize) {
+        let header = SlabHeader::from_addr(slab);
+        if header.next != 0 {
+            SlabHeader::from_addr(header.next).prev = header.prev;
+        }
+        if header.prev == 0 {
+            self.caches[class].partial = header.next;
+        } else {
+            SlabHeader::from_addr(header.prev).next = header.next;
+        }
+        header.prev = 0;
+        header.next = 0;
+    }
+
+    // every arena of the heap begins at a page
+    fn slab_of(&self, addr: usize) -> usize {
+        addr & !(BLOCK_UNIT_SIZE - 1)
+    }
+}
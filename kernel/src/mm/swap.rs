@@ -0,0 +1,124 @@
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use fs::block_dev::BlockDevice;
+use lazy_static::lazy_static;
+
+use crate::mm::address::{PhysPageNum, VirtPageNum};
+use crate::mm::memory_set::{MemorySet, PAGE_SIZE};
+use crate::sbi::virtio_blk::{BLOCK_SZ, VIRTIO0, VirtIOBlock};
+use crate::sync::safe_cell_single::SafeCellSingle;
+use crate::task::manager::all_tasks;
+
+const BLOCKS_PER_PAGE: usize = PAGE_SIZE / BLOCK_SZ;
+
+// a page-sized slot in the swap area, freed when dropped
+pub struct SwapSlot {
+    id: usize,
+}
+
+pub struct SwapManager {
+    device: Option<Arc<dyn BlockDevice>>,
+    used: Vec<bool>,
+    // clock hand: (token of the memory set, vpn) where the next scan begins
+    hand: (usize, VirtPageNum),
+}
+
+impl SwapManager {
+    pub fn new() -> Self {
+        Self {
+            device: None,
+            used: Vec::new(),
+            hand: (0, VirtPageNum(0)),
+        }
+    }
+
+    pub fn init(&mut self, device: Arc<dyn BlockDevice>, slot_num: usize) {
+        self.device = Some(device);
+        self.used = vec![false; slot_num];
+    }
+
+    fn alloc(&mut self) -> Option<usize> {
+        let id = self.used.iter().position(|used| !used)?;
+        self.used[id] = true;
+        Some(id)
+    }
+
+    fn dealloc(&mut self, id: usize) {
+        assert!(self.used[id], "[kernel] Swap slot {} has not been allocated!", id);
+        self.used[id] = false;
+    }
+}
+
+lazy_static! {
+    pub static ref SWAP_MANAGER: SafeCellSingle<SwapManager> = unsafe { SafeCellSingle::new(SwapManager::new()) };
+}
+
+impl SwapSlot {
+    pub fn write(&self, ppn: PhysPageNum) {
+        let device = SWAP_MANAGER.borrow_exclusive().device.clone().unwrap();
+        for (i, block) in ppn.get_bytes_array().chunks(BLOCK_SZ).enumerate() {
+            device.write_block(self.id * BLOCKS_PER_PAGE + i, block);
+        }
+    }
+
+    pub fn read(&self, ppn: PhysPageNum) {
+        let device = SWAP_MANAGER.borrow_exclusive().device.clone().unwrap();
+        for (i, block) in ppn.get_bytes_array().chunks_mut(BLOCK_SZ).enumerate() {
+            device.read_block(self.id * BLOCKS_PER_PAGE + i, block);
+        }
+    }
+}
+
+impl Drop for SwapSlot {
+    fn drop(&mut self) {
+        SWAP_MANAGER.borrow_exclusive().dealloc(self.id);
+    }
+}
+
+pub fn init_swap() {
+    let device = VirtIOBlock::new(VIRTIO0);
+    let slot_num = device.capacity() / BLOCKS_PER_PAGE;
+    SWAP_MANAGER.borrow_exclusive().init(Arc::new(device), slot_num);
+    println!("[kernel] swap: {} pages", slot_num);
+}
+
+pub fn slot_alloc() -> Option<SwapSlot> {
+    SWAP_MANAGER.borrow_exclusive().alloc().map(|id| SwapSlot { id })
+}
+
+// evict one user page by the second chance (clock) policy, return false if there is no victim.
+// memory sets of busy tasks cannot be borrowed here, so the caller may pass the one it is holding
+pub fn swap_out(current: Option<&mut MemorySet>) -> bool {
+    let tasks = all_tasks();
+    let mut inners: Vec<_> = tasks.iter().filter_map(|task| task.try_borrow_exclusive_inner()).collect();
+    let mut memory_sets: Vec<&mut MemorySet> = inners.iter_mut().map(|inner| &mut inner.memory_set).collect();
+    if let Some(memory_set) = current {
+        memory_sets.push(memory_set);
+    }
+    if memory_sets.is_empty() {
+        return false;
+    }
+    memory_sets.sort_by_key(|memory_set| memory_set.token());
+    let (hand_token, hand_vpn) = SWAP_MANAGER.borrow_exclusive().hand;
+    let start = memory_sets.iter().position(|memory_set| memory_set.token() >= hand_token).unwrap_or(0);
+    let len = memory_sets.len();
+    let mut victim = None;
+    // accessed bits are cleared in the first round, so the hand stops within two rounds
+    for i in 0..len * 2 + 1 {
+        let memory_set = &mut memory_sets[(start + i) % len];
+        let vpn = if i == 0 && memory_set.token() == hand_token { hand_vpn } else { VirtPageNum(0) };
+        if let Some(vpn) = memory_set.swap_out(vpn) {
+            victim = Some((memory_set.token(), VirtPageNum(vpn.0 + 1)));
+            break;
+        }
+    }
+    match victim {
+        Some(hand) => {
+            SWAP_MANAGER.borrow_exclusive().hand = hand;
+            true
+        }
+        None => false,
+    }
+}
@@ -0,0 +1,130 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::mem::size_of;
+
+use crate::mm::address::VirtAddr;
+use crate::mm::area::MapPermission;
+use crate::mm::memory_set::{MemorySet, PAGE_SIZE};
+
+// strings from user space, e.g. paths, are cut off beyond this length
+pub const USER_STR_MAX_LEN: usize = 4096;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum UserError {
+    Fault, // not mapped, not a user page, or without the permission
+    TooLong,
+    Invalid, // e.g. a string not in utf-8
+}
+
+// a value in user space, checked on every access
+pub struct UserPtr<T> {
+    addr: usize,
+    _marker: PhantomData<T>,
+}
+
+// a byte buffer in user space, possibly spanning pages
+pub struct UserSlice {
+    addr: usize,
+    len: usize,
+}
+
+// a NUL-terminated string in user space
+pub struct UserStr {
+    addr: usize,
+}
+
+impl<T: Copy> UserPtr<T> {
+    pub fn new(ptr: *const T) -> Self {
+        Self { addr: ptr as usize, _marker: PhantomData }
+    }
+
+    pub fn read(&self, memory_set: &mut MemorySet) -> Result<T, UserError> {
+        let mut value = core::mem::MaybeUninit::<T>::uninit();
+        let bytes = unsafe { core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, size_of::<T>()) };
+        UserSlice::new(self.addr as *const u8, size_of::<T>()).copy_to(memory_set, bytes)?;
+        Ok(unsafe { value.assume_init() })
+    }
+
+    pub fn write(&self, memory_set: &mut MemorySet, value: T) -> Result<(), UserError> {
+        let bytes = unsafe { core::slice::from_raw_parts(&value as *const T as *const u8, size_of::<T>()) };
+        UserSlice::new(self.addr as *const u8, size_of::<T>()).copy_from(memory_set, bytes)
+    }
+}
+
+impl UserSlice {
+    pub fn new(ptr: *const u8, len: usize) -> Self {
+        Self { addr: ptr as usize, len }
+    }
+
+    // fault in the pages of the buffer and check them for the access
+    pub fn check(&self, memory_set: &mut MemorySet, access: MapPermission) -> Result<(), UserError> {
+        if memory_set.prepare_access(self.addr, self.len, access) { Ok(()) } else { Err(UserError::Fault) }
+    }
+
+    // the buffer split at page boundaries, valid until the memory set changes
+    pub fn buffers(&self, memory_set: &mut MemorySet, access: MapPermission) -> Result<Vec<&'static mut [u8]>, UserError> {
+        self.check(memory_set, access)?;
+        let mut buffers = Vec::new();
+        let mut va = self.addr;
+        let end = self.addr + self.len;
+        while va < end {
+            let page_end = (VirtAddr::from(va).floor().0 + 1) * PAGE_SIZE;
+            let ppn = memory_set.translate(VirtAddr::from(va).floor()).ok_or(UserError::Fault)?.ppn();
+            let offset = VirtAddr::from(va).page_offset();
+            buffers.push(&mut ppn.get_bytes_array()[offset..offset + (page_end.min(end) - va)]);
+            va = page_end.min(end);
+        }
+        Ok(buffers)
+    }
+
+    pub fn copy_to(&self, memory_set: &mut MemorySet, dst: &mut [u8]) -> Result<(), UserError> {
+        let mut copied = 0;
+        for buffer in self.buffers(memory_set, MapPermission::R)? {
+            dst[copied..copied + buffer.len()].copy_from_slice(buffer);
+            copied += buffer.len();
+        }
+        Ok(())
+    }
+
+    pub fn copy_from(&self, memory_set: &mut MemorySet, src: &[u8]) -> Result<(), UserError> {
+        let mut copied = 0;
+        for buffer in self.buffers(memory_set, MapPermission::W)? {
+            buffer.copy_from_slice(&src[copied..copied + buffer.len()]);
+            copied += buffer.len();
+        }
+        Ok(())
+    }
+
+    pub fn read_vec(&self, memory_set: &mut MemorySet) -> Result<Vec<u8>, UserError> {
+        let mut data = alloc::vec![0u8; self.len];
+        self.copy_to(memory_set, &mut data)?;
+        Ok(data)
+    }
+}
+
+impl UserStr {
+    pub fn new(ptr: *const u8) -> Self {
+        Self { addr: ptr as usize }
+    }
+
+    // bytes are taken page by page, so the string may end right before an unmapped page
+    pub fn read(&self, memory_set: &mut MemorySet) -> Result<String, UserError> {
+        let mut bytes = Vec::new();
+        let mut va = self.addr;
+        loop {
+            let page_end = (VirtAddr::from(va).floor().0 + 1) * PAGE_SIZE;
+            let chunk = UserSlice::new(va as *const u8, page_end - va).buffers(memory_set, MapPermission::R)?;
+            for &ch in chunk.iter().flat_map(|buffer| buffer.iter()) {
+                if ch == 0 {
+                    return String::from_utf8(bytes).map_err(|_| UserError::Invalid);
+                }
+                if bytes.len() == USER_STR_MAX_LEN {
+                    return Err(UserError::TooLong);
+                }
+                bytes.push(ch);
+            }
+            va = page_end;
+        }
+    }
+}
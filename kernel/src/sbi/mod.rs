@@ -2,7 +2,10 @@ use core::fmt;
 use core::fmt::Write;
 use core::sync::atomic::{AtomicPtr, Ordering};
 
+pub mod plic;
+pub mod rtc;
 mod uart;
+pub mod virtio_blk;
 
 const SHUT_DOWN_ADDR: usize = 0x100000;
 const SHUT_DOWN_FLAG: u32 = 0x5555;
@@ -11,8 +14,32 @@ pub fn print(args: fmt::Arguments) {
     uart::UART.borrow_exclusive().write_fmt(args).unwrap();
 }
 
-pub fn recv() -> u8 {
-    unsafe { uart::UART.borrow_exclusive().recv() }
+pub fn send(data: u8) {
+    uart::UART.borrow_exclusive().send(data);
+}
+
+// a byte received by the UART interrupt, without waiting
+pub fn recv() -> Option<u8> {
+    uart::RX_BUFFER.borrow_exclusive().pop()
+}
+
+// claim the pending sources until none is left
+pub fn handle_external_interrupt() {
+    while let Some(irq) = plic::claim() {
+        match irq {
+            plic::UART_IRQ => {
+                let uart = uart::UART.borrow_exclusive();
+                let mut rx_buffer = uart::RX_BUFFER.borrow_exclusive();
+                while let Some(data) = uart.recv() {
+                    rx_buffer.push(data);
+                }
+            }
+            _ => {
+                println!("[kernel] Unexpected external interrupt {}", irq);
+            }
+        }
+        plic::complete(irq);
+    }
 }
 
 
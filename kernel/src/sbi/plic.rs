@@ -0,0 +1,39 @@
+use core::ptr::{read_volatile, write_volatile};
+
+pub const PLIC_BASE: usize = 0x0c00_0000;
+pub const UART_IRQ: u32 = 10;
+
+// register offsets, a context is a privilege mode of a hart
+const PRIORITY: usize = 0x0; // 4 bytes per source
+const ENABLE: usize = 0x2000; // 0x80 bytes per context
+const THRESHOLD: usize = 0x20_0000; // 0x1000 bytes per context
+const CLAIM: usize = 0x20_0004; // also for completion
+const S_CONTEXT: usize = 1; // S-mode of hart 0
+
+fn read_reg(offset: usize) -> u32 {
+    unsafe { read_volatile((PLIC_BASE + offset) as *const u32) }
+}
+
+fn write_reg(offset: usize, value: u32) {
+    unsafe { write_volatile((PLIC_BASE + offset) as *mut u32, value) }
+}
+
+// route the UART interrupt to S-mode, the PLIC must be mapped
+pub fn init() {
+    write_reg(PRIORITY + 4 * UART_IRQ as usize, 1);
+    let enable = ENABLE + 0x80 * S_CONTEXT + 4 * (UART_IRQ as usize / 32);
+    write_reg(enable, read_reg(enable) | 1 << (UART_IRQ % 32));
+    write_reg(THRESHOLD + 0x1000 * S_CONTEXT, 0);
+}
+
+// the pending source with the highest priority, None if there is none
+pub fn claim() -> Option<u32> {
+    match read_reg(CLAIM + 0x1000 * S_CONTEXT) {
+        0 => None,
+        irq => Some(irq),
+    }
+}
+
+pub fn complete(irq: u32) {
+    write_reg(CLAIM + 0x1000 * S_CONTEXT, irq);
+}
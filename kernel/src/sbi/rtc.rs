@@ -0,0 +1,16 @@
+use core::ptr::read_volatile;
+
+// the Goldfish RTC of the virt machine, mapped through MMIO
+pub const RTC_BASE: usize = 0x0010_1000;
+
+const TIME_LOW: usize = 0x00;
+const TIME_HIGH: usize = 0x04; // latched when TIME_LOW is read
+
+// ns since the epoch
+pub fn read_time_ns() -> usize {
+    unsafe {
+        let low = read_volatile((RTC_BASE + TIME_LOW) as *const u32) as usize;
+        let high = read_volatile((RTC_BASE + TIME_HIGH) as *const u32) as usize;
+        high << 32 | low
+    }
+}
@@ -4,6 +4,7 @@ use lazy_static::lazy_static;
 use crate::sync::safe_cell_single::SafeCellSingle;
 
 const UART_BASE: usize = 0x10000000;
+const RX_BUFFER_SIZE: usize = 256;
 
 macro_rules! wait_for {
     ($cond:expr) => {
@@ -51,15 +52,10 @@ impl UartRegs {
     const LCR_EIGHT_BITS: u8 = 0b11;
     const FCR_FIFO_ENABLE: u8 = 0x01;
     const FCR_FIFO_CLEAR: u8 = 0x06;
-    const IER_TX_ENABLE: u8 = 0x02;
     const IER_RX_ENABLE: u8 = 0x01;
     const OUTPUT_EMPTY: u8 = 0x20;
     const INPUT_AVAILABLE: u8 = 0x01;
 
-    // other constant
-    const BS: u8 = 0x08;
-    const DEL: u8 = 0xff;
-
     pub fn new(base: usize) -> Self {
         let base_ptr = base as *mut u8;
         let mut regs: [AtomicPtr<u8>; 8] = Default::default();
@@ -78,7 +74,7 @@ impl UartRegs {
         self.write_reg(Self::DLM, Self::MSB_BAUD_RATE);// MSB for baud rate of 38.4K
         self.write_reg(Self::LCR, Self::LCR_EIGHT_BITS);// leave set-baud mode, and set word length to 8 bits, no parity
         self.write_reg(Self::FCR, Self::FCR_FIFO_ENABLE | Self::FCR_FIFO_CLEAR);// reset and enable FIFOs
-        self.write_reg(Self::IER, Self::IER_TX_ENABLE | Self::IER_RX_ENABLE); // enable transmit and receive interrupts
+        self.write_reg(Self::IER, Self::IER_RX_ENABLE); // enable receive interrupts, sending is polled
     }
 
     fn read_reg(&self, id: usize) -> u8 {
@@ -93,30 +89,53 @@ impl UartRegs {
 
     // Send a byte on the serial port
     pub fn send(&self, data: u8) {
-        match data {
-            Self::BS | Self::DEL => {
-                wait_for!((self.read_reg(Self::LSR) & Self::OUTPUT_EMPTY) != 0);
-                self.write_reg(Self::THR, Self::BS);
-                wait_for!((self.read_reg(Self::LSR) & Self::OUTPUT_EMPTY) != 0);
-                self.write_reg(Self::THR, b' ');
-                wait_for!((self.read_reg(Self::LSR) & Self::OUTPUT_EMPTY) != 0);
-                self.write_reg(Self::THR, Self::BS);
-            }
-            _ => {
-                wait_for!((self.read_reg(Self::LSR) & Self::OUTPUT_EMPTY) != 0);
-                self.write_reg(Self::THR, data);
-            }
+        wait_for!((self.read_reg(Self::LSR) & Self::OUTPUT_EMPTY) != 0);
+        self.write_reg(Self::THR, data);
+    }
+
+    // None if no byte has been received
+    pub fn recv(&self) -> Option<u8> {
+        if self.read_reg(Self::LSR) & Self::INPUT_AVAILABLE != 0 {
+            Some(self.read_reg(Self::RBR))
+        } else {
+            None
         }
     }
+}
 
-    pub unsafe fn recv(&self) -> u8 {
-        wait_for!((self.read_reg(Self::LSR) & Self::OUTPUT_EMPTY) != 0);
-        self.read_reg(Self::RBR)
+// filled by the receive interrupt, bytes are dropped when it is full
+pub struct RxBuffer {
+    buf: [u8; RX_BUFFER_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl RxBuffer {
+    pub fn new() -> Self {
+        Self { buf: [0; RX_BUFFER_SIZE], head: 0, len: 0 }
+    }
+
+    pub fn push(&mut self, data: u8) {
+        if self.len < RX_BUFFER_SIZE {
+            self.buf[(self.head + self.len) % RX_BUFFER_SIZE] = data;
+            self.len += 1;
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let data = self.buf[self.head];
+        self.head = (self.head + 1) % RX_BUFFER_SIZE;
+        self.len -= 1;
+        Some(data)
     }
 }
 
 lazy_static! {
    pub static ref UART: SafeCellSingle<UartRegs> = unsafe { SafeCellSingle::new(UartRegs::new(UART_BASE))};
+   pub static ref RX_BUFFER: SafeCellSingle<RxBuffer> = unsafe { SafeCellSingle::new(RxBuffer::new()) };
 }
 
 
@@ -0,0 +1,232 @@
+use core::ptr::{addr_of, addr_of_mut, read_volatile, write_volatile};
+use core::sync::atomic::{fence, Ordering};
+
+use fs::block_dev::BlockDevice;
+
+use crate::sync::safe_cell_single::SafeCellSingle;
+
+pub const VIRTIO0: usize = 0x10001000;
+pub const BLOCK_SZ: usize = 512;
+
+const QUEUE_SIZE: usize = 8;
+const PAGE_SIZE: usize = 0x1000;
+
+// virtio-mmio registers
+const MAGIC_VALUE: usize = 0x000;
+const VERSION: usize = 0x004;
+const DEVICE_ID: usize = 0x008;
+const DEVICE_FEATURES: usize = 0x010;
+const DRIVER_FEATURES: usize = 0x020;
+const GUEST_PAGE_SIZE: usize = 0x028; // legacy only
+const QUEUE_SEL: usize = 0x030;
+const QUEUE_NUM_MAX: usize = 0x034;
+const QUEUE_NUM: usize = 0x038;
+const QUEUE_ALIGN: usize = 0x03c; // legacy only
+const QUEUE_PFN: usize = 0x040; // legacy only
+const QUEUE_READY: usize = 0x044;
+const QUEUE_NOTIFY: usize = 0x050;
+const INTERRUPT_STATUS: usize = 0x060;
+const INTERRUPT_ACK: usize = 0x064;
+const STATUS: usize = 0x070;
+const QUEUE_DESC_LOW: usize = 0x080;
+const QUEUE_DESC_HIGH: usize = 0x084;
+const QUEUE_DRIVER_LOW: usize = 0x090;
+const QUEUE_DRIVER_HIGH: usize = 0x094;
+const QUEUE_DEVICE_LOW: usize = 0x0a0;
+const QUEUE_DEVICE_HIGH: usize = 0x0a4;
+const CONFIG_CAPACITY: usize = 0x100;
+
+// control signals
+const MAGIC: u32 = 0x74726976; // "virt"
+const BLOCK_DEVICE_ID: u32 = 2;
+const STATUS_ACKNOWLEDGE: u32 = 1;
+const STATUS_DRIVER: u32 = 2;
+const STATUS_DRIVER_OK: u32 = 4;
+const STATUS_FEATURES_OK: u32 = 8;
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+const BLK_T_IN: u32 = 0;
+const BLK_T_OUT: u32 = 1;
+const BLK_S_OK: u8 = 0;
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct VirtqDesc {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+struct VirtqAvail {
+    flags: u16,
+    idx: u16,
+    ring: [u16; QUEUE_SIZE],
+    used_event: u16,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct VirtqUsedElem {
+    id: u32,
+    len: u32,
+}
+
+// the used ring starts at the next page in the legacy layout
+#[repr(C, align(4096))]
+struct VirtqUsed {
+    flags: u16,
+    idx: u16,
+    ring: [VirtqUsedElem; QUEUE_SIZE],
+    avail_event: u16,
+}
+
+#[repr(C, align(4096))]
+struct VirtQueue {
+    desc: [VirtqDesc; QUEUE_SIZE],
+    avail: VirtqAvail,
+    used: VirtqUsed,
+}
+
+#[repr(C)]
+struct BlkReqHeader {
+    req_type: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+#[repr(C)]
+struct BlkReq {
+    header: BlkReqHeader,
+    data: [u8; BLOCK_SZ],
+    status: u8,
+}
+
+// kernel data is identically mapped (kernel stacks are not), so statics are handed to the device
+// by their addresses and every block is copied through REQUEST
+static mut REQUEST: BlkReq = BlkReq {
+    header: BlkReqHeader { req_type: 0, reserved: 0, sector: 0 },
+    data: [0; BLOCK_SZ],
+    status: 0,
+};
+
+static mut QUEUE: VirtQueue = VirtQueue {
+    desc: [VirtqDesc { addr: 0, len: 0, flags: 0, next: 0 }; QUEUE_SIZE],
+    avail: VirtqAvail { flags: 0, idx: 0, ring: [0; QUEUE_SIZE], used_event: 0 },
+    used: VirtqUsed { flags: 0, idx: 0, ring: [VirtqUsedElem { id: 0, len: 0 }; QUEUE_SIZE], avail_event: 0 },
+};
+
+pub struct VirtIOBlockInner {
+    base: usize,
+    used_idx: u16,
+}
+
+// virtio block device on the mmio bus, requests are served one by one by polling
+pub struct VirtIOBlock {
+    inner: SafeCellSingle<VirtIOBlockInner>,
+}
+
+impl VirtIOBlockInner {
+    fn read_reg(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.base + offset) as *const u32) }
+    }
+
+    fn write_reg(&mut self, offset: usize, value: u32) {
+        unsafe { write_volatile((self.base + offset) as *mut u32, value) }
+    }
+
+    fn init(&mut self) {
+        assert_eq!(self.read_reg(MAGIC_VALUE), MAGIC, "[kernel] virtio: bad magic value");
+        assert_eq!(self.read_reg(DEVICE_ID), BLOCK_DEVICE_ID, "[kernel] virtio: no block device");
+        let legacy = self.read_reg(VERSION) == 1;
+        self.write_reg(STATUS, 0); // reset
+        self.write_reg(STATUS, STATUS_ACKNOWLEDGE);
+        self.write_reg(STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+        let _features = self.read_reg(DEVICE_FEATURES);
+        self.write_reg(DRIVER_FEATURES, 0); // no optional feature is needed
+        if !legacy {
+            self.write_reg(STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK);
+            assert_ne!(self.read_reg(STATUS) & STATUS_FEATURES_OK, 0, "[kernel] virtio: features rejected");
+        }
+        self.write_reg(QUEUE_SEL, 0);
+        assert!(self.read_reg(QUEUE_NUM_MAX) as usize >= QUEUE_SIZE, "[kernel] virtio: queue too short");
+        self.write_reg(QUEUE_NUM, QUEUE_SIZE as u32);
+        let queue = unsafe { addr_of!(QUEUE) as usize };
+        if legacy {
+            self.write_reg(GUEST_PAGE_SIZE, PAGE_SIZE as u32);
+            self.write_reg(QUEUE_ALIGN, PAGE_SIZE as u32);
+            self.write_reg(QUEUE_PFN, (queue / PAGE_SIZE) as u32);
+        } else {
+            let desc = unsafe { addr_of!(QUEUE.desc) as usize };
+            let avail = unsafe { addr_of!(QUEUE.avail) as usize };
+            let used = unsafe { addr_of!(QUEUE.used) as usize };
+            self.write_reg(QUEUE_DESC_LOW, desc as u32);
+            self.write_reg(QUEUE_DESC_HIGH, (desc >> 32) as u32);
+            self.write_reg(QUEUE_DRIVER_LOW, avail as u32);
+            self.write_reg(QUEUE_DRIVER_HIGH, (avail >> 32) as u32);
+            self.write_reg(QUEUE_DEVICE_LOW, used as u32);
+            self.write_reg(QUEUE_DEVICE_HIGH, (used >> 32) as u32);
+            self.write_reg(QUEUE_READY, 1);
+        }
+        let status = self.read_reg(STATUS);
+        self.write_reg(STATUS, status | STATUS_DRIVER_OK);
+    }
+
+    // submit one request (header, data, status) and wait until the device finishes it
+    fn request(&mut self, req_type: u32, block_id: usize) {
+        let data_flags = if req_type == BLK_T_IN { DESC_F_NEXT | DESC_F_WRITE } else { DESC_F_NEXT };
+        unsafe {
+            let queue = &mut *addr_of_mut!(QUEUE);
+            let req = &mut *addr_of_mut!(REQUEST);
+            req.header = BlkReqHeader { req_type, reserved: 0, sector: block_id as u64 };
+            req.status = 0xff;
+            queue.desc[0] = VirtqDesc { addr: addr_of!(req.header) as u64, len: 16, flags: DESC_F_NEXT, next: 1 };
+            queue.desc[1] = VirtqDesc { addr: addr_of!(req.data) as u64, len: BLOCK_SZ as u32, flags: data_flags, next: 2 };
+            queue.desc[2] = VirtqDesc { addr: addr_of!(req.status) as u64, len: 1, flags: DESC_F_WRITE, next: 0 };
+            let avail_idx = read_volatile(addr_of!(queue.avail.idx));
+            write_volatile(addr_of_mut!(queue.avail.ring[avail_idx as usize % QUEUE_SIZE]), 0);
+            fence(Ordering::SeqCst);
+            write_volatile(addr_of_mut!(queue.avail.idx), avail_idx.wrapping_add(1));
+            fence(Ordering::SeqCst);
+            self.write_reg(QUEUE_NOTIFY, 0);
+            while read_volatile(addr_of!(queue.used.idx)) == self.used_idx {
+                core::hint::spin_loop();
+            }
+            fence(Ordering::SeqCst);
+            self.used_idx = self.used_idx.wrapping_add(1);
+            let interrupt = self.read_reg(INTERRUPT_STATUS);
+            self.write_reg(INTERRUPT_ACK, interrupt);
+            assert_eq!(read_volatile(addr_of!(req.status)), BLK_S_OK, "[kernel] virtio: block {} io error", block_id);
+        }
+    }
+}
+
+impl VirtIOBlock {
+    pub fn new(base: usize) -> Self {
+        let mut inner = VirtIOBlockInner { base, used_idx: 0 };
+        inner.init();
+        Self { inner: unsafe { SafeCellSingle::new(inner) } }
+    }
+
+    // number of blocks
+    pub fn capacity(&self) -> usize {
+        let inner = self.inner.borrow_exclusive();
+        inner.read_reg(CONFIG_CAPACITY) as usize | (inner.read_reg(CONFIG_CAPACITY + 4) as usize) << 32
+    }
+}
+
+impl BlockDevice for VirtIOBlock {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        assert_eq!(buf.len(), BLOCK_SZ);
+        self.inner.borrow_exclusive().request(BLK_T_IN, block_id);
+        buf.copy_from_slice(unsafe { &*addr_of!(REQUEST.data) });
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        assert_eq!(buf.len(), BLOCK_SZ);
+        let mut inner = self.inner.borrow_exclusive();
+        unsafe { (*addr_of_mut!(REQUEST.data)).copy_from_slice(buf); }
+        inner.request(BLK_T_OUT, block_id);
+    }
+}
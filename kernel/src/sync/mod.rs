@@ -1 +1,2 @@
-pub mod safe_cell_single;
\ No newline at end of file
+pub mod safe_cell_single;
+pub mod wait_queue;
\ No newline at end of file
@@ -14,6 +14,10 @@ impl<T> SafeCellSingle<T> {
     pub fn borrow_exclusive(&self) -> RefMut<'_, T> {
         self.inner.borrow_mut()
     }
+
+    pub fn try_borrow_exclusive(&self) -> Option<RefMut<'_, T>> {
+        self.inner.try_borrow_mut().ok()
+    }
 }
 
 
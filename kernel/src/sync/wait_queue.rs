@@ -0,0 +1,29 @@
+use alloc::collections::VecDeque;
+
+use crate::task::wake_task;
+
+// tasks blocked on an event, a woken task checks its condition again since the wake-up may be spurious
+pub struct WaitQueue {
+    pids: VecDeque<usize>,
+}
+
+impl WaitQueue {
+    pub fn new() -> Self {
+        Self { pids: VecDeque::new() }
+    }
+
+    // the task should block right after, with the queue released
+    pub fn add(&mut self, pid: usize) {
+        if !self.pids.contains(&pid) {
+            self.pids.push_back(pid);
+        }
+    }
+
+    pub fn remove(&mut self, pid: usize) {
+        self.pids.retain(|&p| p != pid);
+    }
+
+    pub fn wake_all(&mut self) {
+        self.pids.drain(..).for_each(wake_task);
+    }
+}
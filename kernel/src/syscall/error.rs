@@ -0,0 +1,50 @@
+use crate::mm::elf::ElfError;
+use crate::mm::user_ptr::UserError;
+
+// errno values as in Linux, a syscall returns the negative one on failure
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[repr(isize)]
+pub enum SysError {
+    EPERM = 1,
+    ENOENT = 2,
+    ESRCH = 3,
+    EINTR = 4,
+    E2BIG = 7,
+    ENOEXEC = 8,
+    EBADF = 9,
+    ECHILD = 10,
+    EAGAIN = 11,
+    ENOMEM = 12,
+    EFAULT = 14,
+    EINVAL = 22,
+    ENOTTY = 25,
+    ENAMETOOLONG = 36,
+    ENOSYS = 38,
+}
+
+pub type SysResult = Result<usize, SysError>;
+
+impl SysError {
+    pub fn errno(self) -> isize {
+        -(self as isize)
+    }
+}
+
+impl From<UserError> for SysError {
+    fn from(err: UserError) -> Self {
+        match err {
+            UserError::Fault => SysError::EFAULT,
+            UserError::TooLong => SysError::ENAMETOOLONG,
+            UserError::Invalid => SysError::EINVAL,
+        }
+    }
+}
+
+impl From<ElfError> for SysError {
+    fn from(err: ElfError) -> Self {
+        match err {
+            ElfError::OutOfMemory => SysError::ENOMEM,
+            _ => SysError::ENOEXEC,
+        }
+    }
+}
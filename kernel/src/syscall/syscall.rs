@@ -1,71 +1,80 @@
 use alloc::string::String;
+use alloc::vec::Vec;
 use core::slice::SliceIndex;
 
 use crate::loader::get_app_data_by_name;
-use crate::mm::address::VirtAddr;
-use crate::mm::page_table::{PageTable, translated_byte_buffer};
-use crate::task::{add_task, current_task, current_user_token, exit_current_and_run_next, suspend_current_and_run_next};
-use crate::task::manager::remove_task;
-use crate::timer::{get_time, get_time_ms};
+use crate::mm::area::MapPermission;
+use crate::mm::frame_allocator::{frame_free_num, frame_total_num};
+use crate::mm::memory_set::{ARG_MAX, HEAP_TOP, MemorySet, PAGE_SIZE};
+use crate::mm::user_ptr::{UserPtr, UserSlice, UserStr};
+use crate::mm::shm::{SHM_MANAGER, SHMMAX};
+use crate::syscall::error::{SysError, SysResult};
+use crate::task::{add_task, block_current_and_run_next, current_task, exit_current_and_run_next, suspend_current_and_run_next};
+use crate::task::coredump::CORE_DUMP;
+use crate::task::manager::{all_tasks, pid2task};
+use crate::task::signal::{interrupted, NSIG, send_signal, SIG_BLOCK, SIG_DFL, SIG_IGN, SIG_SETMASK, SIG_UNBLOCK, SigAction, SignalFrame, SIGKILL, SIGSTOP, UNBLOCKABLE};
+use crate::timer::{CLOCK_MONOTONIC, CLOCK_REALTIME, get_realtime_ns, get_time, get_time_ns, ITIMER_MAX_SEC, ITimerVal, NSEC_PER_SEC, TIMER_QUEUE, TimerEvent, TimeSpec, TimeVal, USEC_PER_SEC};
+use crate::tty::{Termios, TTY};
 
 const FD_STDIN: usize = 0;
 const FD_STDOUT: usize = 1;
-const SIGKILL: u8 = 9;
+const WNOHANG: usize = 1;
+const WUNTRACED: usize = 2;
+const TCGETS: usize = 0x5401;
+const TCSETS: usize = 0x5402;
+const TIOCGPGRP: usize = 0x540f;
+const TIOCSPGRP: usize = 0x5410;
+const PROT_MASK: usize = 0x7; // PROT_READ | PROT_WRITE | PROT_EXEC
+const MAP_ANONYMOUS: usize = 0x20;
+const IPC_CREAT: usize = 0o1000;
+const IPC_RMID: usize = 0;
+const SHM_RDONLY: usize = 0o10000;
+const ITIMER_REAL: usize = 0;
 
-pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct MemInfo {
+    pub total_frames: usize,
+    pub free_frames: usize,
+    pub task_frames: usize, // frames held by the task, page tables included
+}
+
+pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> SysResult {
     match fd {
         FD_STDOUT => {
-            let buffers = translated_byte_buffer(current_user_token(), buf, len);
-            for buffer in buffers {
-                print!("{}", core::str::from_utf8(buffer).unwrap());
-            }
-            len as isize
-        }
-        _ => {
-            panic!("Unsupported fd in sys_write!");
+            let data = UserSlice::new(buf, len).read_vec(&mut current_task().unwrap().borrow_exclusive_inner().memory_set)?;
+            print!("{}", String::from_utf8_lossy(&data));
+            Ok(len)
         }
+        _ => Err(SysError::EBADF),
     }
 }
 
-pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
+// bytes are collected in kernel and copied out at last, since the buffer may be swapped out while waiting
+pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> SysResult {
     match fd {
         FD_STDIN => {
-            return if len == 0 {
-                let mut buffers = translated_byte_buffer(current_user_token(), buf, 1);
-                let ch = crate::sbi::recv();
-                if ch == 0 {
-                    0
-                } else {
-                    buffers[0][0] = ch;
-                    1
+            let cur_task = current_task().unwrap();
+            let user_buf = UserSlice::new(buf, len);
+            user_buf.check(&mut cur_task.borrow_exclusive_inner().memory_set, MapPermission::W)?;
+            let data = loop {
+                let mut tty = TTY.borrow_exclusive();
+                let data = tty.read(len.max(1));
+                if !data.is_empty() || len == 0 { // len = 0 reads a byte without blocking
+                    break data;
                 }
-            } else {
-                let mut buffers = translated_byte_buffer(current_user_token(), buf, len);
-                let mut ch: u8 = 0;
-                let mut index_1 = 0;
-                let mut index_2 = 0;
-                let mut cnt = 0;
-                while cnt < len {
-                    ch = crate::sbi::recv();
-                    if ch == 0 {
-                        suspend_current_and_run_next();
-                        continue;
-                    } else {
-                        buffers[index_1][index_2] = ch;
-                        index_2 += 1;
-                        if index_2 >= buffers[index_1].len() {
-                            index_1 += 1;
-                            index_2 = 0;
-                        }
-                        cnt += 1;
-                    }
+                tty.readers.add(cur_task.pid);
+                drop(tty);
+                block_current_and_run_next();
+                if interrupted() {
+                    return Err(SysError::EINTR);
                 }
-                len as isize
-            }
-        }
-        _ => {
-            panic!("Unsupported fd in sys_read!");
+            };
+            let user_buf = UserSlice::new(buf, data.len());
+            user_buf.copy_from(&mut cur_task.borrow_exclusive_inner().memory_set, &data)?;
+            Ok(data.len())
         }
+        _ => Err(SysError::EBADF),
     }
 }
 
@@ -76,90 +85,423 @@ pub fn sys_exit(exit_code: i32) -> ! {
     panic!("[kernel] Unreachable area in sys_exit!")
 }
 
-pub fn sys_fork() -> isize {
+pub fn sys_fork() -> SysResult {
     let current_task = current_task().unwrap();
-    let new_task = current_task.fork();
+    let new_task = current_task.fork().ok_or(SysError::ENOMEM)?;
     let new_pid = new_task.pid;
     println!("[kernel] Application forked (parent pid = {}, child pid = {})", current_task.pid, new_pid);
     let trap_cx = new_task.borrow_exclusive_inner().get_trap_cx();
     trap_cx.x[10] = 0;  // a0 =0
     add_task(new_task);
-    new_pid as isize
+    Ok(new_pid)
 }
 
-pub fn sys_exec(path: *const u8) -> isize {
-    let cur_task = current_task().unwrap();
-    let token = cur_task.borrow_exclusive_inner().get_user_token();
-    let tmp_page_table = PageTable::new_tmp(token);
-    let mut path_str = String::new();
-    let mut va = path as usize;
+// read a NULL-terminated array of strings, the size of all arrays read is limited by ARG_MAX
+fn read_str_array(memory_set: &mut MemorySet, ptr: *const usize, size: &mut usize) -> Result<Vec<String>, SysError> {
+    let mut strs = Vec::new();
+    if ptr.is_null() {
+        return Ok(strs);
+    }
     loop {
-        let pa = tmp_page_table.translate_va(VirtAddr::from(va)).unwrap();
-        let ch: u8 = *(pa.get_mut());
-        if ch == 0 {
-            break;
-        } else {
-            path_str.push(ch as char);
-            va += 1;
+        let str_ptr = UserPtr::new(ptr.wrapping_add(strs.len())).read(memory_set)?;
+        if str_ptr == 0 {
+            return Ok(strs);
         }
+        let s = UserStr::new(str_ptr as *const u8).read(memory_set)?;
+        *size += s.len() + 1 + core::mem::size_of::<usize>();
+        if *size > ARG_MAX {
+            return Err(SysError::E2BIG);
+        }
+        strs.push(s);
     }
+}
+
+// argv or envp can be null, which stands for an empty array
+pub fn sys_exec(path: *const u8, argv: *const usize, envp: *const usize) -> SysResult {
+    let cur_task = current_task().unwrap();
+    let mut inner = cur_task.borrow_exclusive_inner();
+    let path_str = UserStr::new(path).read(&mut inner.memory_set)?;
+    let mut size = 0;
+    let args = read_str_array(&mut inner.memory_set, argv, &mut size)?;
+    let envs = read_str_array(&mut inner.memory_set, envp, &mut size)?;
+    drop(inner);
     println!("[kernel] Application executed (pid = {}, path = {})", cur_task.pid, path_str.as_str());
-    if let Some(data) = get_app_data_by_name(path_str.as_str()) {
-        cur_task.exec(data);
-        0
-    } else {
-        -1
+    let data = get_app_data_by_name(path_str.as_str()).ok_or(SysError::ENOENT)?;
+    if let Err(err) = cur_task.exec(data, &args, &envs) {
+        println!("[kernel] Failed to load {} (pid = {}): {:?}", path_str.as_str(), cur_task.pid, err);
+        return Err(err.into());
     }
+    Ok(0)
 }
 
-pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
+// block until a child exits, unless WNOHANG which gives EAGAIN instead
+// with WUNTRACED, a stopped pid is reported once with exit code (signo << 8) | 0x7f
+pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32, options: usize) -> SysResult {
     let cur_task = current_task().unwrap();
-    cur_task.waitpid(pid, exit_code_ptr)
+    loop {
+        if options & WUNTRACED != 0 && pid > 0 {
+            if let Some(task) = pid2task(pid as usize) {
+                let mut inner = task.borrow_exclusive_inner();
+                if inner.ppid != cur_task.pid {
+                    return Err(SysError::ECHILD);
+                }
+                let stop_signal = inner.signals.stop_signal.take();
+                drop(inner);
+                drop(task);
+                if let Some(signo) = stop_signal {
+                    let exit_code = (signo << 8 | 0x7f) as i32;
+                    UserPtr::new(exit_code_ptr as *const i32).write(&mut cur_task.borrow_exclusive_inner().memory_set, exit_code)?;
+                    return Ok(pid as usize);
+                }
+            }
+        }
+        match cur_task.waitpid(pid, exit_code_ptr) {
+            Err(SysError::EAGAIN) if options & WNOHANG == 0 => {
+                block_current_and_run_next(); // woken by the exit or stop of a child
+                if interrupted() {
+                    return Err(SysError::EINTR);
+                }
+            }
+            result => return result,
+        }
+    }
 }
 
-pub fn sys_yield() -> isize {
+pub fn sys_yield() -> SysResult {
     suspend_current_and_run_next();
-    0
-}
-
-pub fn sys_kill(pid: usize, signal: u8) -> isize {
-    return match signal {
-        SIGKILL => {
-            if pid == 0 {
-                println!("[kernel] Initproc cannot be killed!");
-                -1
-            } else if pid == 1 {
-                println!("[kernel] Manager cannot be killed!");
-                -1
-            } else {
-                let cur_pid = current_task().unwrap().pid;
-                if cur_pid == pid {
-                    println!("[kernel] Application (pid = {}) is killed by pid = {}.", pid, cur_pid);
-                    exit_current_and_run_next(SIGKILL as i32);
-                } else {
-                    if let Some(kill_task) = remove_task(pid) {
-                        kill_task.exit(SIGKILL as i32);
-                    } else {
-                        println!("[kernel] No application with pid = {}!", pid);
-                        return -1;
-                    }
-                }
-                println!("[kernel] Application (pid = {}) is killed by pid = {}.", pid, cur_pid);
-                0
+    Ok(0)
+}
+
+// signo = 0 only checks that the task exists
+pub fn sys_kill(pid: usize, signo: usize) -> SysResult {
+    if signo >= NSIG {
+        return Err(SysError::EINVAL);
+    }
+    if pid == 0 || pid == 1 {
+        println!("[kernel] Initproc and manager cannot be signaled!");
+        return Err(SysError::EPERM);
+    }
+    let task = pid2task(pid).ok_or(SysError::ESRCH)?;
+    if signo != 0 {
+        send_signal(&task, signo);
+    }
+    Ok(0)
+}
+
+pub fn sys_sigaction(signo: usize, act: *const SigAction, old_act: *mut SigAction) -> SysResult {
+    if signo == 0 || signo >= NSIG || (!act.is_null() && (signo == SIGKILL || signo == SIGSTOP)) {
+        return Err(SysError::EINVAL);
+    }
+    let cur_task = current_task().unwrap();
+    let mut inner = cur_task.borrow_exclusive_inner();
+    let old = inner.signals.actions[signo];
+    if !act.is_null() {
+        let action = UserPtr::new(act).read(&mut inner.memory_set)?;
+        if action.handler != SIG_DFL && action.handler != SIG_IGN && action.restorer == 0 {
+            return Err(SysError::EINVAL); // the handler could not return
+        }
+        inner.signals.actions[signo] = action;
+    }
+    if !old_act.is_null() {
+        UserPtr::new(old_act as *const SigAction).write(&mut inner.memory_set, old)?;
+    }
+    Ok(0)
+}
+
+pub fn sys_sigprocmask(how: usize, set: *const u32, old_set: *mut u32) -> SysResult {
+    let cur_task = current_task().unwrap();
+    let mut inner = cur_task.borrow_exclusive_inner();
+    let old = inner.signals.blocked;
+    if !set.is_null() {
+        let set = UserPtr::new(set).read(&mut inner.memory_set)?;
+        inner.signals.blocked = match how {
+            SIG_BLOCK => old | set,
+            SIG_UNBLOCK => old & !set,
+            SIG_SETMASK => set,
+            _ => return Err(SysError::EINVAL),
+        } & !UNBLOCKABLE;
+    }
+    if !old_set.is_null() {
+        UserPtr::new(old_set as *const u32).write(&mut inner.memory_set, old)?;
+    }
+    Ok(0)
+}
+
+// return from a handler, the frame is at sp, as the restorer is called when the handler returns
+pub fn sys_sigreturn() -> SysResult {
+    let cur_task = current_task().unwrap();
+    let mut inner = cur_task.borrow_exclusive_inner();
+    let trap_cx = inner.get_trap_cx();
+    let frame = UserPtr::new(trap_cx.x[2] as *const SignalFrame).read(&mut inner.memory_set)?;
+    trap_cx.x = frame.x;
+    trap_cx.sepc = frame.sepc;
+    inner.signals.blocked = frame.blocked & !UNBLOCKABLE;
+    Ok(frame.x[10]) // a0 is written with the return value
+}
+
+// only the terminal on stdin and stdout
+pub fn sys_ioctl(fd: usize, cmd: usize, arg: usize) -> SysResult {
+    if fd != FD_STDIN && fd != FD_STDOUT {
+        return Err(SysError::EBADF);
+    }
+    let cur_task = current_task().unwrap();
+    let mut inner = cur_task.borrow_exclusive_inner();
+    match cmd {
+        TCGETS => {
+            let termios = TTY.borrow_exclusive().termios();
+            UserPtr::new(arg as *const Termios).write(&mut inner.memory_set, termios)?;
+        }
+        TCSETS => {
+            let termios = UserPtr::new(arg as *const Termios).read(&mut inner.memory_set)?;
+            TTY.borrow_exclusive().set_termios(termios);
+        }
+        TIOCGPGRP => { // pid_t as in linux
+            let pgid = TTY.borrow_exclusive().fg_pgid.unwrap_or(0) as i32;
+            UserPtr::new(arg as *const i32).write(&mut inner.memory_set, pgid)?;
+        }
+        TIOCSPGRP => {
+            let pgid = UserPtr::new(arg as *const i32).read(&mut inner.memory_set)?;
+            drop(inner);
+            if pgid < 0 {
+                return Err(SysError::EINVAL);
+            }
+            let pgid = pgid as usize;
+            if !group_exists(pgid) {
+                return Err(SysError::EPERM);
             }
+            TTY.borrow_exclusive().fg_pgid = Some(pgid);
         }
-        _ => {
-            println!("[kernel] Unsupported signal!");
-            -1
+        _ => return Err(SysError::ENOTTY),
+    }
+    Ok(0)
+}
+
+// initproc and manager are never in a group with others
+fn group_exists(pgid: usize) -> bool {
+    all_tasks().iter().any(|task| task.pid > 1 && task.borrow_exclusive_inner().pgid == pgid)
+}
+
+// pid = 0 stands for the current task, pgid = 0 for the pid, a group is joined only if it exists
+// only the current task or one of its children may be moved
+pub fn sys_setpgid(pid: usize, pgid: usize) -> SysResult {
+    let cur_task = current_task().unwrap();
+    let cur_pid = cur_task.pid;
+    let task = if pid == 0 || pid == cur_pid { cur_task } else { pid2task(pid).ok_or(SysError::ESRCH)? };
+    if task.pid != cur_pid && task.borrow_exclusive_inner().ppid != cur_pid {
+        return Err(SysError::ESRCH);
+    }
+    if task.pid <= 1 {
+        return Err(SysError::EPERM);
+    }
+    let pgid = if pgid == 0 { task.pid } else { pgid };
+    if pgid != task.pid && !group_exists(pgid) {
+        return Err(SysError::EPERM);
+    }
+    task.borrow_exclusive_inner().pgid = pgid;
+    Ok(0)
+}
+
+pub fn sys_getpgid(pid: usize) -> SysResult {
+    let cur_task = current_task().unwrap();
+    let task = if pid == 0 || pid == cur_task.pid { cur_task } else { pid2task(pid).ok_or(SysError::ESRCH)? };
+    let pgid = task.borrow_exclusive_inner().pgid;
+    Ok(pgid)
+}
+
+// the time left is written to rem if a signal interrupts the sleep, rem can be null
+pub fn sys_nanosleep(req: *const TimeSpec, rem: *mut TimeSpec) -> SysResult {
+    let cur_task = current_task().unwrap();
+    let req = UserPtr::new(req).read(&mut cur_task.borrow_exclusive_inner().memory_set)?;
+    if req.tv_nsec >= NSEC_PER_SEC {
+        return Err(SysError::EINVAL);
+    }
+    let deadline = get_time().saturating_add(req.to_ticks());
+    loop {
+        // added again each time, as the timer may have fired while the task was stopped
+        let mut timers = TIMER_QUEUE.borrow_exclusive();
+        timers.cancel(TimerEvent::Wake(cur_task.pid));
+        if get_time() >= deadline {
+            return Ok(0);
+        }
+        timers.add(deadline, TimerEvent::Wake(cur_task.pid));
+        drop(timers);
+        block_current_and_run_next();
+        if interrupted() {
+            TIMER_QUEUE.borrow_exclusive().cancel(TimerEvent::Wake(cur_task.pid));
+            if !rem.is_null() {
+                let left = TimeSpec::from_ticks(deadline.saturating_sub(get_time()));
+                UserPtr::new(rem as *const TimeSpec).write(&mut cur_task.borrow_exclusive_inner().memory_set, left)?;
+            }
+            return Err(SysError::EINTR);
         }
+    }
+}
+
+// CLOCK_REALTIME counts from the epoch, CLOCK_MONOTONIC from boot
+pub fn sys_clock_gettime(clock_id: usize, tp: *mut TimeSpec) -> SysResult {
+    let ns = match clock_id {
+        CLOCK_REALTIME => get_realtime_ns(),
+        CLOCK_MONOTONIC => get_time_ns(),
+        _ => return Err(SysError::EINVAL),
     };
-    0
+    UserPtr::new(tp as *const TimeSpec).write(&mut current_task().unwrap().borrow_exclusive_inner().memory_set, TimeSpec::from_ns(ns))?;
+    Ok(0)
 }
 
-pub fn sys_get_time() -> isize {
-    get_time_ms() as isize
+// only ITIMER_REAL, which sends SIGALRM
+pub fn sys_getitimer(which: usize, curr_value: *mut ITimerVal) -> SysResult {
+    if which != ITIMER_REAL {
+        return Err(SysError::EINVAL);
+    }
+    let cur_task = current_task().unwrap();
+    let (deadline, interval) = TIMER_QUEUE.borrow_exclusive().alarm(cur_task.pid);
+    let value = itimer_value(deadline, interval);
+    UserPtr::new(curr_value as *const ITimerVal).write(&mut cur_task.borrow_exclusive_inner().memory_set, value)?;
+    Ok(0)
 }
 
-pub fn sys_getpid() -> isize {
-    current_task().unwrap().pid as isize
+// a zero it_value disarms the timer, a zero it_interval makes it one-shot
+pub fn sys_setitimer(which: usize, new_value: *const ITimerVal, old_value: *mut ITimerVal) -> SysResult {
+    if which != ITIMER_REAL {
+        return Err(SysError::EINVAL);
+    }
+    let cur_task = current_task().unwrap();
+    let new_value = UserPtr::new(new_value).read(&mut cur_task.borrow_exclusive_inner().memory_set)?;
+    let (value, interval) = (new_value.it_value, new_value.it_interval);
+    if value.tv_usec >= USEC_PER_SEC || interval.tv_usec >= USEC_PER_SEC ||
+        value.tv_sec > ITIMER_MAX_SEC || interval.tv_sec > ITIMER_MAX_SEC {
+        return Err(SysError::EINVAL);
+    }
+    let value = value.to_ticks();
+    let deadline = if value == 0 { 0 } else { get_time().saturating_add(value) };
+    let (old_deadline, old_interval) = TIMER_QUEUE.borrow_exclusive().set_alarm(cur_task.pid, deadline, interval.to_ticks());
+    if !old_value.is_null() {
+        let old = itimer_value(old_deadline, old_interval);
+        UserPtr::new(old_value as *const ITimerVal).write(&mut cur_task.borrow_exclusive_inner().memory_set, old)?;
+    }
+    Ok(0)
+}
+
+fn itimer_value(deadline: Option<usize>, interval: usize) -> ITimerVal {
+    ITimerVal {
+        it_interval: TimeVal::from_ticks(interval),
+        it_value: TimeVal::from_ticks(deadline.map_or(0, |deadline| deadline.saturating_sub(get_time()).max(1))),
+    }
+}
+
+// the obsolete timezone is not supported, tz should be null
+pub fn sys_gettimeofday(tv: *mut TimeVal, _tz: usize) -> SysResult {
+    let tv_value = TimeVal::from_ns(get_realtime_ns());
+    UserPtr::new(tv as *const TimeVal).write(&mut current_task().unwrap().borrow_exclusive_inner().memory_set, tv_value)?;
+    Ok(0)
+}
+
+pub fn sys_getpid() -> SysResult {
+    Ok(current_task().unwrap().pid)
+}
+
+pub fn sys_brk(addr: usize) -> SysResult { // brk(0) returns the current program break
+    if addr > HEAP_TOP {
+        return Err(SysError::ENOMEM);
+    }
+    Ok(current_task().unwrap().borrow_exclusive_inner().memory_set.set_brk(addr))
+}
+
+pub fn sys_mmap(addr: usize, len: usize, prot: usize, flags: usize) -> SysResult {
+    if flags & MAP_ANONYMOUS == 0 || prot & !PROT_MASK != 0 || len == 0 || addr % PAGE_SIZE != 0 ||
+        !MemorySet::in_user_space(addr, len) {
+        return Err(SysError::EINVAL); // only anonymous mapping is supported
+    }
+    let permission = prot_to_permission(prot);
+    current_task().unwrap().borrow_exclusive_inner().memory_set.mmap(addr, len, permission).ok_or(SysError::ENOMEM)
+}
+
+// W without R is reserved in RISC-V page tables, so writable implies readable as in Linux
+fn prot_to_permission(prot: usize) -> MapPermission {
+    let mut permission = MapPermission::from_bits((prot << 1) as u8).unwrap() | MapPermission::U;
+    if permission.contains(MapPermission::W) {
+        permission |= MapPermission::R;
+    }
+    permission
+}
+
+pub fn sys_munmap(addr: usize, len: usize) -> SysResult {
+    if len == 0 || addr % PAGE_SIZE != 0 || !MemorySet::in_user_space(addr, len) {
+        return Err(SysError::EINVAL);
+    }
+    if current_task().unwrap().borrow_exclusive_inner().memory_set.munmap(addr, len) { Ok(0) } else { Err(SysError::EINVAL) }
+}
+
+pub fn sys_mprotect(addr: usize, len: usize, prot: usize) -> SysResult {
+    if prot & !PROT_MASK != 0 || len == 0 || addr % PAGE_SIZE != 0 || !MemorySet::in_user_space(addr, len) {
+        return Err(SysError::EINVAL);
+    }
+    let permission = prot_to_permission(prot);
+    // as in Linux, ENOMEM also stands for a range not fully mapped
+    if current_task().unwrap().borrow_exclusive_inner().memory_set.mprotect(addr, len, permission) { Ok(0) } else { Err(SysError::ENOMEM) }
+}
+
+pub fn sys_shmget(key: usize, size: usize, flags: usize) -> SysResult {
+    if size == 0 || size > SHMMAX {
+        return Err(SysError::EINVAL);
+    }
+    let page_num = (size + PAGE_SIZE - 1) / PAGE_SIZE; // no overflow below SHMMAX
+    SHM_MANAGER.borrow_exclusive().get(key, page_num, flags & IPC_CREAT != 0)
+}
+
+pub fn sys_shmat(id: usize, addr: usize, flags: usize) -> SysResult {
+    if addr % PAGE_SIZE != 0 {
+        return Err(SysError::EINVAL);
+    }
+    let frames = SHM_MANAGER.borrow_exclusive().share_frames(id).ok_or(SysError::EINVAL)?;
+    let permission = if flags & SHM_RDONLY != 0 {
+        MapPermission::R | MapPermission::U
+    } else {
+        MapPermission::R | MapPermission::W | MapPermission::U
+    };
+    current_task().unwrap().borrow_exclusive_inner().memory_set.attach_shm(addr, frames, permission).ok_or(SysError::ENOMEM)
+}
+
+pub fn sys_shmdt(addr: usize) -> SysResult {
+    if current_task().unwrap().borrow_exclusive_inner().memory_set.detach_shm(addr) { Ok(0) } else { Err(SysError::EINVAL) }
+}
+
+// the segment is freed after the last process detaches it
+pub fn sys_shmctl(id: usize, cmd: usize) -> SysResult {
+    if cmd == IPC_RMID && SHM_MANAGER.borrow_exclusive().remove(id) { Ok(0) } else { Err(SysError::EINVAL) }
+}
+
+// pid = -1 stands for the current task
+pub fn sys_meminfo(pid: isize, info: *mut MemInfo) -> SysResult {
+    let cur_task = current_task().unwrap();
+    let task = if pid == -1 || pid == cur_task.pid as isize {
+        cur_task.clone()
+    } else {
+        pid2task(pid as usize).ok_or(SysError::ESRCH)?
+    };
+    let task_frames = match task.try_borrow_exclusive_inner() {
+        Some(inner) => inner.memory_set.frame_num(),
+        None => return Err(SysError::EAGAIN),
+    };
+    drop(task);
+    let info_ptr = UserPtr::new(info as *const MemInfo);
+    let info = MemInfo {
+        total_frames: frame_total_num(),
+        free_frames: frame_free_num(),
+        task_frames,
+    };
+    info_ptr.write(&mut cur_task.borrow_exclusive_inner().memory_set, info)?;
+    Ok(0)
+}
+
+// copy the last core dump to buf as far as it fits, return its full size
+pub fn sys_coredump(buf: *mut u8, len: usize) -> SysResult {
+    let core_dump = CORE_DUMP.borrow_exclusive();
+    let core = match core_dump.as_ref() {
+        Some((_, core)) => core,
+        None => return Err(SysError::ENOENT),
+    };
+    let len = len.min(core.len());
+    UserSlice::new(buf, len).copy_from(&mut current_task().unwrap().borrow_exclusive_inner().memory_set, &core[..len])?;
+    Ok(core.len())
 }
@@ -0,0 +1,105 @@
+use alloc::vec::Vec;
+
+use lazy_static::lazy_static;
+
+use crate::mm::area::MapPermission;
+use crate::mm::memory_set::PAGE_SIZE;
+use crate::sync::safe_cell_single::SafeCellSingle;
+use crate::task::current_task;
+
+const CORE_DUMP_MAX: usize = 0x40_0000; // contents of areas beyond are left out
+const ET_CORE: u16 = 4;
+const EM_RISCV: u16 = 243;
+const EF_RISCV: u32 = 0x5; // RVC and double-float ABI, as user programs built for riscv64gc
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const NT_PRSTATUS: u32 = 1;
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+// struct elf_prstatus of riscv64 in Linux
+const PRSTATUS_SIZE: usize = 376;
+const PRSTATUS_CURSIG: usize = 12;
+const PRSTATUS_PID: usize = 32;
+const PRSTATUS_REG: usize = 112; // pc, then x1 to x31
+
+lazy_static! {
+    // (pid, ELF core file) of the last core dump, read by sys_coredump
+    pub static ref CORE_DUMP: SafeCellSingle<Option<(usize, Vec<u8>)>> = unsafe { SafeCellSingle::new(None) };
+}
+
+// dump the registers and user areas of the current task, which is killed by signal, as an ELF core file
+pub fn dump_core(signal: u32) {
+    *CORE_DUMP.borrow_exclusive() = None; // the last one is freed first
+    let task = current_task().unwrap();
+    let inner = task.borrow_exclusive_inner();
+    let trap_cx = inner.get_trap_cx();
+    let mut prstatus = [0u8; PRSTATUS_SIZE];
+    prstatus[0..4].copy_from_slice(&signal.to_le_bytes()); // si_signo
+    prstatus[PRSTATUS_CURSIG..PRSTATUS_CURSIG + 2].copy_from_slice(&(signal as u16).to_le_bytes());
+    prstatus[PRSTATUS_PID..PRSTATUS_PID + 4].copy_from_slice(&(task.pid as u32).to_le_bytes());
+    for i in 0..32 {
+        let reg = if i == 0 { trap_cx.sepc } else { trap_cx.x[i] };
+        prstatus[PRSTATUS_REG + i * 8..PRSTATUS_REG + i * 8 + 8].copy_from_slice(&reg.to_le_bytes());
+    }
+    let mut areas = inner.memory_set.dump_areas(CORE_DUMP_MAX);
+    drop(inner);
+
+    let mut note = Vec::new();
+    note.extend_from_slice(&5u32.to_le_bytes()); // namesz
+    note.extend_from_slice(&(PRSTATUS_SIZE as u32).to_le_bytes());
+    note.extend_from_slice(&NT_PRSTATUS.to_le_bytes());
+    note.extend_from_slice(b"CORE\0\0\0\0"); // padded to 4 bytes
+    note.extend_from_slice(&prstatus);
+
+    let phnum = 1 + areas.len();
+    let note_offset = EHDR_SIZE + PHDR_SIZE * phnum;
+    let mut offset = align_up(note_offset + note.len());
+    // the whole file is reserved at once, the contents of areas are dropped if it does not fit
+    let mut core = Vec::new();
+    if core.try_reserve_exact(offset + areas.iter().map(|area| area.3.len()).sum::<usize>()).is_err() {
+        areas.iter_mut().for_each(|area| area.3 = Vec::new());
+        if core.try_reserve_exact(offset).is_err() {
+            return; // no core dump
+        }
+    }
+    // ELF header
+    core.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    core.extend_from_slice(&ET_CORE.to_le_bytes());
+    core.extend_from_slice(&EM_RISCV.to_le_bytes());
+    core.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    core.extend_from_slice(&0u64.to_le_bytes()); // e_entry
+    core.extend_from_slice(&(EHDR_SIZE as u64).to_le_bytes()); // e_phoff
+    core.extend_from_slice(&0u64.to_le_bytes()); // e_shoff
+    core.extend_from_slice(&EF_RISCV.to_le_bytes());
+    for half in [EHDR_SIZE, PHDR_SIZE, phnum, 64, 0, 0] { // sizes and numbers of headers
+        core.extend_from_slice(&(half as u16).to_le_bytes());
+    }
+    // program headers
+    push_phdr(&mut core, PT_NOTE, 0, note_offset, 0, note.len(), 0, 4);
+    for (beg, end, perm, data) in areas.iter() {
+        let mut flags = 0;
+        if perm.contains(MapPermission::R) { flags |= 4; }
+        if perm.contains(MapPermission::W) { flags |= 2; }
+        if perm.contains(MapPermission::X) { flags |= 1; }
+        push_phdr(&mut core, PT_LOAD, flags, offset, *beg, data.len(), end - beg, PAGE_SIZE);
+        offset += data.len();
+    }
+    core.extend_from_slice(&note);
+    for (_, _, _, data) in areas.iter() {
+        core.resize(align_up(core.len()), 0);
+        core.extend_from_slice(data);
+    }
+    *CORE_DUMP.borrow_exclusive() = Some((task.pid, core));
+}
+
+fn push_phdr(core: &mut Vec<u8>, p_type: u32, flags: u32, offset: usize, vaddr: usize, filesz: usize, memsz: usize, align: usize) {
+    core.extend_from_slice(&p_type.to_le_bytes());
+    core.extend_from_slice(&flags.to_le_bytes());
+    for word in [offset, vaddr, 0, filesz, memsz, align] {
+        core.extend_from_slice(&(word as u64).to_le_bytes());
+    }
+}
+
+fn align_up(offset: usize) -> usize {
+    (offset + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
+}
@@ -1,12 +1,12 @@
 use alloc::collections::BTreeMap;
-use alloc::sync::Arc;
+use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 
 use lazy_static::lazy_static;
 
 use crate::sync::safe_cell_single::SafeCellSingle;
 use crate::task::rand::LinearCongruentialGenerator;
-use crate::task::task::TaskControlBlock;
+use crate::task::task::{TaskControlBlock, TaskStatus};
 
 const DEFAULT_LOTTERY_SHARE: usize = 100;
 const DEFAULT_PRIORITY: i32 = 10;
@@ -43,6 +43,8 @@ impl Lottery {
 
 pub struct TaskManager {
     user: BTreeMap<usize, Arc<TaskControlBlock>>,
+    stopped: BTreeMap<usize, Arc<TaskControlBlock>>,
+    blocked: BTreeMap<usize, Arc<TaskControlBlock>>,
     server: BTreeMap<isize, Arc<TaskControlBlock>>,
     lottery: Vec<Lottery>,
     wait: Option<Arc<TaskControlBlock>>,
@@ -56,6 +58,8 @@ impl TaskManager {
     pub fn new() -> Self {
         TaskManager {
             user: BTreeMap::new(),
+            stopped: BTreeMap::new(),
+            blocked: BTreeMap::new(),
             server: BTreeMap::new(),
             lottery: Vec::new(),
             wait: None,
@@ -75,22 +79,35 @@ impl TaskManager {
 
     pub fn add_task(&mut self, task: Arc<TaskControlBlock>) {
         if self.server_status == 0 {
-            if self.lottery.iter().find(|&x| x.pid == task.pid).is_none() {
-                self.lottery.push(Lottery::new(task.pid));
-                self.sum_lottery += DEFAULT_LOTTERY_SHARE;
-            }
-            self.user.insert(task.pid, task);
+            self.add_ready(task);
         } else if self.server_status > 0 {
             self.wait = Some(task);
         }
     }
 
+    // in server mode add_task takes the task which made the request, so a woken task comes here
+    pub fn add_ready(&mut self, task: Arc<TaskControlBlock>) {
+        if self.lottery.iter().find(|&x| x.pid == task.pid).is_none() {
+            self.lottery.push(Lottery::new(task.pid));
+            self.sum_lottery += DEFAULT_LOTTERY_SHARE;
+        }
+        self.user.insert(task.pid, task);
+    }
+
+    // nothing to run until an interrupt wakes a task
+    pub fn is_idle(&self) -> bool {
+        self.server_status == 0 && self.user.is_empty()
+    }
+
     pub fn fetch_task(&mut self) -> Option<Arc<TaskControlBlock>> {
         let mut sum = 0;
         for elem in self.lottery.iter_mut() {
             sum += elem.share;
         }
         if self.server_status == 0 {
+            if self.sum_lottery == 0 {
+                return None;
+            }
             let id: usize = self.rand.next() % self.sum_lottery + 1;
             let mut sum: usize = 0;
             let mut pid: usize = 0xffffffff;
@@ -117,6 +134,24 @@ impl TaskManager {
     }
 
 
+    pub fn stop_task(&mut self, task: Arc<TaskControlBlock>) {
+        self.remove_task(task.pid); // out of the lottery
+        self.stopped.insert(task.pid, task);
+    }
+
+    pub fn take_stopped_task(&mut self, pid: usize) -> Option<Arc<TaskControlBlock>> {
+        self.stopped.remove(&pid)
+    }
+
+    pub fn block_task(&mut self, task: Arc<TaskControlBlock>) {
+        self.remove_task(task.pid); // out of the lottery
+        self.blocked.insert(task.pid, task);
+    }
+
+    pub fn take_blocked_task(&mut self, pid: usize) -> Option<Arc<TaskControlBlock>> {
+        self.blocked.remove(&pid)
+    }
+
     pub fn remove_task(&mut self, pid: usize) -> Option<Arc<TaskControlBlock>> {
         let mut to_remove: usize = 0xffffffff;
         for (index, task) in self.lottery.iter().enumerate() {
@@ -126,9 +161,12 @@ impl TaskManager {
             }
         }
         if to_remove != 0xffffffff {
-            self.lottery.remove(to_remove);
+            self.sum_lottery -= self.lottery.remove(to_remove).share;
         }
-        return self.user.remove(&pid);
+        // a blocked task must also be taken out of its wait queue by the caller
+        self.user.remove(&pid)
+            .or_else(|| self.blocked.remove(&pid))
+            .or_else(|| self.stopped.remove(&pid))
     }
 }
 
@@ -138,6 +176,13 @@ lazy_static! {
     };
 }
 
+lazy_static! {
+    // all tasks by pid, including the running and waiting ones which are not in TASK_MANAGER
+    pub static ref PID2TASK: SafeCellSingle<BTreeMap<usize, Weak<TaskControlBlock>>> = unsafe {
+        SafeCellSingle::new(BTreeMap::new())
+    };
+}
+
 
 pub fn set_server(pid: usize) {
     TASK_MANAGER.borrow_exclusive().set_server(pid as isize);
@@ -151,6 +196,14 @@ pub fn add_task(task: Arc<TaskControlBlock>) {
     TASK_MANAGER.borrow_exclusive().add_task(task);
 }
 
+pub fn add_ready(task: Arc<TaskControlBlock>) {
+    TASK_MANAGER.borrow_exclusive().add_ready(task);
+}
+
+pub fn is_idle() -> bool {
+    TASK_MANAGER.borrow_exclusive().is_idle()
+}
+
 pub fn add_server(task: Arc<TaskControlBlock>) {
     TASK_MANAGER.borrow_exclusive().add_server(task);
 }
@@ -161,4 +214,41 @@ pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
 
 pub fn remove_task(pid: usize) -> Option<Arc<TaskControlBlock>> {
     TASK_MANAGER.borrow_exclusive().remove_task(pid)
-}
\ No newline at end of file
+}
+
+pub fn stop_task(task: Arc<TaskControlBlock>) {
+    TASK_MANAGER.borrow_exclusive().stop_task(task);
+}
+
+pub fn take_stopped_task(pid: usize) -> Option<Arc<TaskControlBlock>> {
+    TASK_MANAGER.borrow_exclusive().take_stopped_task(pid)
+}
+
+pub fn block_task(task: Arc<TaskControlBlock>) {
+    TASK_MANAGER.borrow_exclusive().block_task(task);
+}
+
+pub fn take_blocked_task(pid: usize) -> Option<Arc<TaskControlBlock>> {
+    TASK_MANAGER.borrow_exclusive().take_blocked_task(pid)
+}
+
+pub fn register_task(task: &Arc<TaskControlBlock>) {
+    PID2TASK.borrow_exclusive().insert(task.pid, Arc::downgrade(task));
+}
+
+pub fn pid2task(pid: usize) -> Option<Arc<TaskControlBlock>> {
+    PID2TASK.borrow_exclusive().get(&pid).and_then(|task| task.upgrade())
+}
+
+// also used when reporting kernel faults, so nothing is borrowed unless it is free
+pub fn task_status(pid: usize) -> Option<TaskStatus> {
+    let task = PID2TASK.try_borrow_exclusive()?.get(&pid)?.upgrade()?;
+    let status = task.try_borrow_exclusive_inner()?.task_status;
+    Some(status)
+}
+
+pub fn all_tasks() -> Vec<Arc<TaskControlBlock>> {
+    let mut pid2task = PID2TASK.borrow_exclusive();
+    pid2task.retain(|_, task| task.strong_count() > 0); // drop the released ones
+    pid2task.values().filter_map(|task| task.upgrade()).collect()
+}
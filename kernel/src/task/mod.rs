@@ -3,15 +3,20 @@ use alloc::sync::{Arc, Weak};
 use lazy_static::lazy_static;
 
 pub use manager::{add_task, is_fixed};
-pub use processor::{current_task, current_trap_cx, current_user_token, run_tasks};
+pub use processor::{current_task, current_trap_cx, current_user_satp, current_user_token, run_tasks};
 
 use crate::loader::get_app_data_by_name;
+use crate::mm::address::VirtAddr;
+use crate::mm::area::{FaultResult, MapPermission};
 use crate::task::context::TaskContext;
-use crate::task::manager::add_server;
+use crate::task::manager::{add_ready, add_server, all_tasks, block_task, register_task, remove_task, stop_task, take_blocked_task, take_stopped_task};
 use crate::task::processor::{schedule, take_current_task};
 use crate::task::task::{TaskControlBlock, TaskStatus};
+use crate::tty::TTY;
 
-mod stack;
+pub mod coredump;
+pub mod signal;
+pub mod stack;
 mod task;
 pub mod manager;
 mod processor;
@@ -19,6 +24,8 @@ mod switch;
 mod context;
 mod rand;
 
+const OOM_EXIT_CODE: i32 = 9; // as if killed by SIGKILL
+
 lazy_static! {
     pub static ref INITPROC: Arc<TaskControlBlock> = Arc::new(
         TaskControlBlock::new_proc_special(get_app_data_by_name("initproc").unwrap(), 0)
@@ -32,6 +39,8 @@ lazy_static! {
 }
 
 pub fn init_proc() {
+    register_task(&INITPROC);
+    register_task(&MANAGER);
     add_task(INITPROC.clone());
     add_server(MANAGER.clone());
 }
@@ -47,6 +56,94 @@ pub fn suspend_current_and_run_next() {
     schedule(task_cx_ptr);
 }
 
+// like suspend, but the task is not scheduled until resume_task
+pub fn stop_current_and_run_next() {
+    let task = take_current_task().unwrap();
+    let mut task_inner = task.borrow_exclusive_inner();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    task_inner.task_status = TaskStatus::Stopped;
+    drop(task_inner);
+    stop_task(task);
+    schedule(task_cx_ptr);
+}
+
+pub fn resume_task(pid: usize) {
+    if let Some(task) = take_stopped_task(pid) {
+        let mut task_inner = task.borrow_exclusive_inner();
+        task_inner.task_status = TaskStatus::Ready;
+        task_inner.signals.stop_signal = None;
+        drop(task_inner);
+        add_ready(task);
+    }
+}
+
+// the task sleeps until wake_task, it should be in a wait queue before
+// a task with a deliverable signal returns at once, so no signal is missed before blocking
+pub fn block_current_and_run_next() {
+    if current_task().unwrap().borrow_exclusive_inner().signals.deliverable() != 0 {
+        return;
+    }
+    let task = take_current_task().unwrap();
+    let mut task_inner = task.borrow_exclusive_inner();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    task_inner.task_status = TaskStatus::Blocked;
+    drop(task_inner);
+    block_task(task);
+    schedule(task_cx_ptr);
+}
+
+pub fn wake_task(pid: usize) {
+    if let Some(task) = take_blocked_task(pid) {
+        task.borrow_exclusive_inner().task_status = TaskStatus::Ready;
+        add_ready(task);
+    }
+}
+
+pub fn handle_page_fault(va: usize, access: MapPermission) -> bool {
+    loop {
+        let task = current_task().unwrap();
+        let result = task.borrow_exclusive_inner().memory_set.handle_page_fault(VirtAddr::from(va).floor(), access);
+        let pid = task.pid;
+        drop(task); // exit does not return here
+        match result {
+            FaultResult::Handled => return true,
+            FaultResult::Invalid => return false,
+            FaultResult::StackOverflow => {
+                println!("[kernel] Stack overflow in application (pid = {}), bad addr = {:#x}, killed.", pid, va);
+                exit_current_and_run_next(-2); // page fault exit code
+            }
+            FaultResult::OutOfMemory => {
+                if !oom_kill(pid) {
+                    println!("[kernel] Out of memory, application (pid = {}) is killed.", pid);
+                    exit_current_and_run_next(OOM_EXIT_CODE);
+                }
+            }
+        }
+    }
+}
+
+// kill the task holding the most frames, return false if the current task should be killed instead
+fn oom_kill(cur_pid: usize) -> bool {
+    let victim = all_tasks()
+        .into_iter()
+        .filter(|task| task.pid > 1) // initproc and manager are never chosen
+        .max_by_key(|task| task.try_borrow_exclusive_inner().map_or(0, |inner| inner.memory_set.frame_num()));
+    match victim {
+        Some(victim) if victim.pid != cur_pid => {
+            match remove_task(victim.pid) { // a ready, blocked or stopped task
+                Some(victim) => {
+                    TTY.borrow_exclusive().readers.remove(victim.pid);
+                    println!("[kernel] Out of memory, application (pid = {}) is killed.", victim.pid);
+                    victim.exit(OOM_EXIT_CODE);
+                    true
+                }
+                None => false,
+            }
+        }
+        _ => false,
+    }
+}
+
 pub fn exit_current_and_run_next(exit_code: i32) {
     current_task().unwrap().exit(exit_code);
     take_current_task();// move curr-task
@@ -4,10 +4,11 @@ use lazy_static::lazy_static;
 
 use crate::sync::safe_cell_single::SafeCellSingle;
 use crate::task::context::TaskContext;
-use crate::task::manager::fetch_task;
+use crate::task::manager::{fetch_task, is_idle};
 use crate::task::switch::__switch;
 use crate::task::task::{TaskControlBlock, TaskStatus};
 use crate::trap::context::TrapContext;
+use crate::trap::wait_for_interrupt;
 
 pub struct Processor {
     //The task currently executing on the current processor
@@ -43,6 +44,11 @@ pub fn current_user_token() -> usize {
         .borrow_exclusive_inner().get_user_token()
 }
 
+pub fn current_user_satp() -> usize {
+    PROCESSOR.borrow_exclusive().current.as_ref().map(Arc::clone).unwrap()
+        .borrow_exclusive_inner().memory_set.user_satp()
+}
+
 pub fn take_current_task() -> Option<Arc<TaskControlBlock>> { // move cur_task
     PROCESSOR.borrow_exclusive().current.take()
 }
@@ -68,6 +74,9 @@ pub fn run_tasks() {
                     next_task_cx_ptr,
                 );
             }
+        } else if is_idle() {
+            drop(processor);
+            wait_for_interrupt();
         }
     }
 }
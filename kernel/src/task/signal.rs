@@ -0,0 +1,244 @@
+use alloc::sync::Arc;
+use core::mem::size_of;
+
+use crate::mm::user_ptr::UserPtr;
+use crate::task::{current_task, exit_current_and_run_next, resume_task, stop_current_and_run_next, wake_task};
+use crate::task::manager::all_tasks;
+use crate::task::task::{TaskControlBlock, TaskStatus};
+
+pub const NSIG: usize = 32; // signals 1 to 31
+pub const SIGINT: usize = 2;
+pub const SIGQUIT: usize = 3;
+pub const SIGILL: usize = 4;
+pub const SIGKILL: usize = 9;
+pub const SIGSEGV: usize = 11;
+pub const SIGALRM: usize = 14;
+pub const SIGTERM: usize = 15;
+pub const SIGCHLD: usize = 17;
+pub const SIGCONT: usize = 18;
+pub const SIGSTOP: usize = 19;
+pub const SIGTSTP: usize = 20;
+pub const SIGTTIN: usize = 21;
+pub const SIGTTOU: usize = 22;
+pub const SIGURG: usize = 23;
+pub const SIGWINCH: usize = 28;
+
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+pub const SA_NODEFER: usize = 0x4000_0000;
+pub const SIG_BLOCK: usize = 0;
+pub const SIG_UNBLOCK: usize = 1;
+pub const SIG_SETMASK: usize = 2;
+
+const STOP_MASK: u32 = 1 << SIGSTOP | 1 << SIGTSTP | 1 << SIGTTIN | 1 << SIGTTOU;
+pub const UNBLOCKABLE: u32 = 1 << SIGKILL | 1 << SIGSTOP;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum DefaultAction {
+    Terminate,
+    Ignore,
+    Stop,
+    Continue,
+}
+
+// the restorer is called when the handler returns, it should do sigreturn
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct SigAction {
+    pub handler: usize,
+    pub mask: u32, // blocked during the handler, besides the signal itself
+    pub flags: usize,
+    pub restorer: usize,
+}
+
+// saved on the user stack when a handler is entered, restored by sigreturn
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct SignalFrame {
+    pub x: [usize; 32],
+    pub sepc: usize,
+    pub blocked: u32,
+}
+
+#[derive(Copy, Clone)]
+pub struct SignalState {
+    pub pending: u32,
+    pub blocked: u32,
+    pub actions: [SigAction; NSIG],
+    pub stop_signal: Option<usize>, // the signal which stopped the task, until waitpid reports it
+}
+
+impl SignalState {
+    pub fn new() -> Self {
+        Self { pending: 0, blocked: 0, actions: [SigAction::default(); NSIG], stop_signal: None }
+    }
+
+    // kept by fork, except the pending signals
+    pub fn fork(&self) -> Self {
+        Self { pending: 0, stop_signal: None, ..*self }
+    }
+
+    pub fn deliverable(&self) -> u32 {
+        self.pending & !(self.blocked & !UNBLOCKABLE)
+    }
+
+    // an ignored signal is discarded when sent, so it never interrupts a blocking syscall
+    fn is_ignored(&self, signo: usize) -> bool {
+        match self.actions[signo].handler {
+            _ if signo == SIGKILL || signo == SIGSTOP => false,
+            SIG_IGN => true,
+            SIG_DFL => default_action(signo) == DefaultAction::Ignore,
+            _ => false,
+        }
+    }
+
+    // handlers are gone with the old program, ignored signals stay ignored
+    pub fn exec(&mut self) {
+        for action in self.actions.iter_mut().filter(|action| action.handler != SIG_IGN) {
+            *action = SigAction::default();
+        }
+    }
+}
+
+pub fn default_action(signo: usize) -> DefaultAction {
+    match signo {
+        SIGCHLD | SIGURG | SIGWINCH => DefaultAction::Ignore,
+        SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU => DefaultAction::Stop,
+        SIGCONT => DefaultAction::Continue,
+        _ => DefaultAction::Terminate,
+    }
+}
+
+// a stop and SIGCONT cancel each other, SIGKILL and SIGCONT wake a stopped task, any queued signal wakes a blocked one
+pub fn send_signal(task: &Arc<TaskControlBlock>, signo: usize) {
+    let mut inner = task.borrow_exclusive_inner();
+    if signo == SIGCONT {
+        inner.signals.pending &= !STOP_MASK;
+    } else if STOP_MASK & 1 << signo != 0 {
+        inner.signals.pending &= !(1 << SIGCONT);
+    }
+    let queued = !inner.signals.is_ignored(signo);
+    if queued {
+        inner.signals.pending |= 1 << signo;
+    }
+    let status = inner.task_status;
+    drop(inner);
+    match status {
+        TaskStatus::Stopped if signo == SIGKILL || signo == SIGCONT => resume_task(task.pid),
+        TaskStatus::Blocked if queued => wake_task(task.pid),
+        _ => {}
+    }
+}
+
+// initproc and manager are never in a group with others
+pub fn send_signal_to_group(pgid: usize, signo: usize) {
+    for task in all_tasks().iter().filter(|task| task.pid > 1) {
+        let in_group = task.borrow_exclusive_inner().pgid == pgid;
+        if in_group {
+            send_signal(task, signo);
+        }
+    }
+}
+
+// make a fault of the current task a signal if the task catches it, otherwise the fault kills the task
+pub fn catch_fault(signo: usize) -> bool {
+    let task = current_task().unwrap();
+    let mut inner = task.borrow_exclusive_inner();
+    let handler = inner.signals.actions[signo].handler;
+    if handler == SIG_DFL || handler == SIG_IGN || inner.signals.blocked & 1 << signo != 0 {
+        return false;
+    }
+    inner.signals.pending |= 1 << signo;
+    true
+}
+
+// the parent may be in waitpid with WUNTRACED
+fn stop_current(signo: usize) {
+    let task = current_task().unwrap();
+    let mut inner = task.borrow_exclusive_inner();
+    inner.signals.stop_signal = Some(signo);
+    let ppid = inner.ppid;
+    drop(inner);
+    drop(task);
+    wake_task(ppid);
+    stop_current_and_run_next();
+}
+
+// checked by a blocking syscall after it is woken, true if it should return EINTR,
+// which is for a handler or a termination, while a stop is taken here and the syscall goes on after SIGCONT
+pub fn interrupted() -> bool {
+    loop {
+        let task = current_task().unwrap();
+        let mut inner = task.borrow_exclusive_inner();
+        let deliverable = inner.signals.deliverable();
+        if deliverable == 0 {
+            return false;
+        }
+        let signo = deliverable.trailing_zeros() as usize;
+        let handler = if signo == SIGKILL || signo == SIGSTOP { SIG_DFL } else { inner.signals.actions[signo].handler };
+        match (handler, default_action(signo)) {
+            (SIG_IGN, _) | (SIG_DFL, DefaultAction::Ignore | DefaultAction::Continue) => {
+                inner.signals.pending &= !(1 << signo);
+            }
+            (SIG_DFL, DefaultAction::Stop) => {
+                inner.signals.pending &= !(1 << signo);
+                drop(inner);
+                drop(task);
+                stop_current(signo);
+            }
+            _ => return true, // left pending for handle_signals
+        }
+    }
+}
+
+// called right before returning to user mode, a handler is entered for at most one signal each time
+pub fn handle_signals() {
+    loop {
+        let task = current_task().unwrap();
+        let mut inner = task.borrow_exclusive_inner();
+        let deliverable = inner.signals.deliverable();
+        if deliverable == 0 {
+            return;
+        }
+        let signo = deliverable.trailing_zeros() as usize;
+        inner.signals.pending &= !(1 << signo);
+        let action = if signo == SIGKILL || signo == SIGSTOP { SigAction::default() } else { inner.signals.actions[signo] };
+        let pid = task.pid;
+        match action.handler {
+            SIG_IGN => continue,
+            SIG_DFL => {
+                drop(inner);
+                drop(task);
+                match default_action(signo) {
+                    DefaultAction::Ignore | DefaultAction::Continue => {}
+                    DefaultAction::Stop => stop_current(signo),
+                    DefaultAction::Terminate => {
+                        println!("[kernel] Application (pid = {}) is killed by signal {}.", pid, signo);
+                        exit_current_and_run_next(signo as i32);
+                    }
+                }
+            }
+            handler => {
+                let trap_cx = inner.get_trap_cx();
+                let frame = SignalFrame { x: trap_cx.x, sepc: trap_cx.sepc, blocked: inner.signals.blocked };
+                let sp = (trap_cx.x[2] - size_of::<SignalFrame>()) & !0xf;
+                if UserPtr::new(sp as *const SignalFrame).write(&mut inner.memory_set, frame).is_err() {
+                    drop(inner);
+                    drop(task);
+                    println!("[kernel] Bad signal stack of application (pid = {}), killed.", pid);
+                    exit_current_and_run_next(SIGSEGV as i32);
+                    return;
+                }
+                inner.signals.blocked |= action.mask;
+                if action.flags & SA_NODEFER == 0 {
+                    inner.signals.blocked |= 1 << signo;
+                }
+                trap_cx.x[1] = action.restorer; // ra
+                trap_cx.x[2] = sp;
+                trap_cx.x[10] = signo; // a0
+                trap_cx.sepc = handler;
+                return;
+            }
+        }
+    }
+}
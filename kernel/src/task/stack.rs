@@ -5,6 +5,8 @@ use crate::mm::memory_set::KERNEL_SPACE;
 pub const TRAMPOLINE: usize = usize::MAX - 0x1000 + 1;
 pub const PAGE_SIZE: usize = 0x1000;
 pub const KERNEL_STACK_SIZE: usize = 2 * PAGE_SIZE;
+#[cfg(feature = "stack_canary")]
+const STACK_CANARY: usize = 0xdead_beef_dead_beef;
 
 pub struct KernelStack {
     pid: usize,
@@ -12,7 +14,7 @@ pub struct KernelStack {
 
 
 impl KernelStack {
-    pub fn new(pid: usize) -> Self {
+    pub fn new(pid: usize) -> Option<Self> {
         let (kernel_stack_top, kernel_stack_bottom) = KernelStack::get_stack_pos(pid);
         KERNEL_SPACE
             .borrow_exclusive()
@@ -20,10 +22,34 @@ impl KernelStack {
                 kernel_stack_bottom.into(),
                 kernel_stack_top.into(),
                 MapPermission::R | MapPermission::W,
-            );
-        KernelStack {
-            pid: pid,
+            )?;
+        #[cfg(feature = "stack_canary")]
+        for addr in (kernel_stack_bottom..kernel_stack_top).step_by(core::mem::size_of::<usize>()) {
+            unsafe { *(addr as *mut usize) = STACK_CANARY; }
         }
+        Some(KernelStack {
+            pid: pid,
+        })
+    }
+
+    // the pid whose stack lies right above the guard page containing addr
+    pub fn guard_page_owner(addr: usize) -> Option<usize> {
+        let unit = KERNEL_STACK_SIZE + PAGE_SIZE;
+        let pid = ((TRAMPOLINE - PAGE_SIZE).checked_sub(addr)? + unit - 1) / unit;
+        let pid = pid.checked_sub(2)?;
+        let (_, bottom) = KernelStack::get_stack_pos(pid);
+        if bottom - PAGE_SIZE <= addr && addr < bottom { Some(pid) } else { None }
+    }
+
+    // bytes of the stack ever written, the untouched part still holds the canary
+    #[cfg(feature = "stack_canary")]
+    pub fn used_size(&self) -> usize {
+        let (top, bottom) = KernelStack::get_stack_pos(self.pid);
+        let untouched = (bottom..top)
+            .step_by(core::mem::size_of::<usize>())
+            .find(|&addr| unsafe { *(addr as *const usize) } != STACK_CANARY)
+            .unwrap_or(top);
+        top - untouched
     }
 
     pub fn get_stack_pos(pid: usize) -> (usize, usize) {
@@ -39,6 +65,8 @@ impl KernelStack {
 
 impl Drop for KernelStack {
     fn drop(&mut self) {
+        #[cfg(feature = "stack_canary")]
+        println!("[kernel] pid {} used {} of {} bytes of kernel stack", self.pid, self.used_size(), KERNEL_STACK_SIZE);
         let (_, kernel_stack_bottom) = KernelStack::get_stack_pos(self.pid);
         KERNEL_SPACE
             .borrow_exclusive()
@@ -1,16 +1,21 @@
+use alloc::string::String;
 use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 use core::cell::RefMut;
 
 use crate::mm::address::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
+use crate::mm::elf::ElfError;
 use crate::mm::frame_allocator::BUFFER_BEG;
 use crate::mm::memory_set::{BUFFER, KERNEL_SPACE, MemorySet, PAGE_SIZE, TRAP_CONTEXT};
-use crate::mm::page_table::translated_refmut;
+use crate::mm::user_ptr::UserPtr;
 use crate::sync::safe_cell_single::SafeCellSingle;
-use crate::task::{context, suspend_current_and_run_next};
+use crate::syscall::error::{SysError, SysResult};
+use crate::task::{context, suspend_current_and_run_next, wake_task};
 use crate::task::context::TaskContext;
-use crate::task::manager::set_server;
+use crate::task::manager::{pid2task, register_task, set_server};
+use crate::task::signal::{send_signal, SignalState, SIGCHLD};
 use crate::task::stack::{KernelStack, TRAMPOLINE};
+use crate::timer::TIMER_QUEUE;
 use crate::trap::context::TrapContext;
 use crate::trap::trap_handler;
 
@@ -19,6 +24,7 @@ const FORK_REQUEST: usize = 1;
 const EXIT_REQUEST: usize = 2;
 const WAITPID_REQUEST: usize = 3;
 const DONE_REQUEST: usize = 4;
+const RELEASE_REQUEST: usize = 5;
 
 pub struct TaskControlBlock {
     pub pid: usize,
@@ -32,12 +38,17 @@ pub struct TaskControlBlockInner {
     pub task_cx: TaskContext,
     pub task_status: TaskStatus,
     pub memory_set: MemorySet,
+    pub signals: SignalState,
+    pub pgid: usize, // the process group, inherited by fork
+    pub ppid: usize, // the parent at fork, woken when the task stops
 }
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum TaskStatus {
     Ready,
     Running,
+    Stopped, // by a stop signal, until SIGCONT or SIGKILL
+    Blocked, // in a wait queue, until the event or a signal
     Zombie,
 }
 
@@ -59,13 +70,14 @@ impl TaskControlBlockInner {
 
 
 impl TaskControlBlock {
-    pub fn new_proc_special(elf_data: &[u8], pid: usize) -> Self {
+    pub fn new_proc_special(elf_data: &'static [u8], pid: usize) -> Self {
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (mut memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let (mut memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data).unwrap();
+        let user_sp = memory_set.init_stack(user_sp, &[], &[]).unwrap();
         let trap_cx_ppn = memory_set.translate(VirtAddr::from(TRAP_CONTEXT).into()).unwrap().ppn();
-        let kernel_stack = KernelStack::new(pid); //init_proc: pid=0
+        let kernel_stack = KernelStack::new(pid).unwrap(); //init_proc: pid=0
         let (kernel_stack_top, _) = KernelStack::get_stack_pos(pid);
-        memory_set.map_buffer_user(pid);
+        memory_set.map_buffer_user(pid).unwrap();
         // push a task context which goes to trap_return to the top of kernel stack
         let task_control_block = Self {
             pid: pid,
@@ -77,6 +89,9 @@ impl TaskControlBlock {
                     task_cx: TaskContext::new_trap_return(kernel_stack_top),
                     task_status: TaskStatus::Ready,
                     memory_set: memory_set,
+                    signals: SignalState::new(),
+                    pgid: pid,
+                    ppid: 0,
                 })
             },
         };
@@ -96,10 +111,16 @@ impl TaskControlBlock {
         self.inner.borrow_exclusive()
     }
 
-    pub fn fork(self: &Arc<TaskControlBlock>) -> Arc<TaskControlBlock> {
+    pub fn try_borrow_exclusive_inner(&self) -> Option<RefMut<'_, TaskControlBlockInner>> {
+        self.inner.try_borrow_exclusive()
+    }
+
+    // return None if memory runs out
+    pub fn fork(self: &Arc<TaskControlBlock>) -> Option<Arc<TaskControlBlock>> {
         let mut parent_inner = self.borrow_exclusive_inner();
-        let mut memory_set = MemorySet::new_from_exist(&parent_inner.memory_set); // TODO: COW
+        let memory_set = MemorySet::new_from_exist(&mut parent_inner.memory_set);
         drop(parent_inner);
+        let mut memory_set = memory_set?;
         let trap_cx_ppn = memory_set.translate(VirtAddr::from(TRAP_CONTEXT).into()).unwrap().ppn();
         let buffer_usize = unsafe {
             core::slice::from_raw_parts_mut((BUFFER_BEG + PAGE_SIZE) as *mut usize, PAGE_SIZE / 8)
@@ -111,8 +132,12 @@ impl TaskControlBlock {
         assert_eq!(buffer_usize[0], DONE_REQUEST); // confirm manager work correctly
         let pid = buffer_usize[1];
         let kernel_stack = KernelStack::new(pid);
+        if kernel_stack.is_none() || memory_set.map_buffer_user(pid).is_none() {
+            Self::release_request(pid); // manager has allocated the pid, the parent must not see it
+            return None;
+        }
+        let kernel_stack = kernel_stack.unwrap();
         let (kernel_stack_top, _) = KernelStack::get_stack_pos(pid);
-        memory_set.map_buffer_user(pid);
         let mut parent_inner = self.borrow_exclusive_inner();
         let task_control_block = Arc::new(TaskControlBlock {
             pid: pid,
@@ -124,22 +149,29 @@ impl TaskControlBlock {
                     task_cx: TaskContext::new_trap_return(kernel_stack_top),
                     task_status: TaskStatus::Ready,
                     memory_set: memory_set,
+                    signals: parent_inner.signals.fork(),
+                    pgid: parent_inner.pgid,
+                    ppid: self.pid,
                 })
             },
         });
         // modify kernel_sp in trap_cx, which means child will return to User-mod
         let trap_cx = task_control_block.borrow_exclusive_inner().get_trap_cx();
         trap_cx.kernel_sp = kernel_stack_top;
-        task_control_block
+        register_task(&task_control_block);
+        Some(task_control_block)
     }
 
-    pub fn exec(&self, elf_data: &[u8]) {
-        let (mut memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
-        memory_set.map_buffer_user(self.pid);
+    // the old memory set is kept on failure
+    pub fn exec(&self, elf_data: &'static [u8], args: &[String], envs: &[String]) -> Result<(), ElfError> {
+        let (mut memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data)?;
+        let user_sp = memory_set.init_stack(user_sp, args, envs).ok_or(ElfError::OutOfMemory)?;
+        memory_set.map_buffer_user(self.pid).ok_or(ElfError::OutOfMemory)?;
         let mut inner = self.borrow_exclusive_inner();
         inner.trap_cx_ppn = memory_set.translate(VirtAddr::from(TRAP_CONTEXT).into()).unwrap().ppn();
         inner.memory_set = memory_set; // replace mem_set
         inner.base_size = user_sp;
+        inner.signals.exec();
         let trap_cx = inner.get_trap_cx();
         *trap_cx = TrapContext::init_context(
             entry_point,
@@ -148,9 +180,10 @@ impl TaskControlBlock {
             self.kernel_stack.get_top(),
             trap_handler as usize,
         );
+        Ok(())
     }
 
-    pub fn waitpid(self: &Arc<TaskControlBlock>, pid: isize, exit_code_ptr: *mut i32) -> isize {
+    pub fn waitpid(self: &Arc<TaskControlBlock>, pid: isize, exit_code_ptr: *mut i32) -> SysResult {
         let buffer_usize = unsafe {
             core::slice::from_raw_parts_mut((BUFFER_BEG + PAGE_SIZE) as *mut usize, PAGE_SIZE / 8)
         };
@@ -161,27 +194,55 @@ impl TaskControlBlock {
         suspend_current_and_run_next();
         assert_eq!(buffer_usize[0], DONE_REQUEST); // confirm manager work correctly
         let mut inner = self.borrow_exclusive_inner();
-        let ret = buffer_usize[1] as isize;
-        if ret >= 0 {
-            let exit_code = buffer_usize[2] as i32;
-            *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code; // write to the current user-space
+        match buffer_usize[1] as isize {
+            -1 => Err(SysError::ECHILD),
+            -2 => Err(SysError::EAGAIN), // the child is still running
+            pid => {
+                let exit_code = buffer_usize[2] as i32;
+                UserPtr::new(exit_code_ptr as *const i32).write(&mut inner.memory_set, exit_code)?;
+                Ok(pid as usize)
+            }
         }
-        ret
     }
 
     pub fn exit(self: &Arc<TaskControlBlock>, exit_code: i32) {
         let mut task_inner = self.borrow_exclusive_inner();
         task_inner.task_status = TaskStatus::Zombie;
         drop(task_inner);
+        TIMER_QUEUE.borrow_exclusive().remove_task(self.pid);
+        let parent_pid = Self::exit_request(self.pid, exit_code);
+        let mut task_inner = self.borrow_exclusive_inner();
+        task_inner.memory_set.recycle();
+        drop(task_inner);
+        if let Some(parent) = pid2task(parent_pid) {
+            send_signal(&parent, SIGCHLD);
+        }
+        wake_task(parent_pid); // the parent may be blocked in waitpid
+    }
+
+    // drop a child which fork failed to create, no exit is reported for it
+    fn release_request(pid: usize) {
+        let buffer_usize = unsafe {
+            core::slice::from_raw_parts_mut((BUFFER_BEG + PAGE_SIZE) as *mut usize, PAGE_SIZE / 8)
+        };
+        buffer_usize[0] = RELEASE_REQUEST;
+        buffer_usize[1] = pid;
+        set_server(1);
+        suspend_current_and_run_next();
+        assert_eq!(buffer_usize[0], DONE_REQUEST); // confirm manager work correctly
+    }
+
+    // return the pid of the parent
+    fn exit_request(pid: usize, exit_code: i32) -> usize {
         let buffer_usize = unsafe {
             core::slice::from_raw_parts_mut((BUFFER_BEG + PAGE_SIZE) as *mut usize, PAGE_SIZE / 8)
         };
         buffer_usize[0] = EXIT_REQUEST;
-        buffer_usize[1] = self.pid;
+        buffer_usize[1] = pid;
         buffer_usize[2] = exit_code as usize;
         set_server(1);
         suspend_current_and_run_next();
-        let mut task_inner = self.borrow_exclusive_inner();
-        task_inner.memory_set.recycle();
+        assert_eq!(buffer_usize[0], DONE_REQUEST); // confirm manager work correctly
+        buffer_usize[1]
     }
 }
@@ -1,12 +1,183 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
 use core::arch::global_asm;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
+use lazy_static::lazy_static;
 use riscv::register::{mhartid, mie, mscratch, mstatus, mtvec, time};
 
+use crate::sbi::rtc;
+use crate::sync::safe_cell_single::SafeCellSingle;
+use crate::task::manager::pid2task;
+use crate::task::signal::{send_signal, SIGALRM};
+use crate::task::wake_task;
+
 pub const TIME_INTERVAL: usize = 100000;
 pub const CLOCK_FREQ: usize = 12500000;
+pub const NSEC_PER_SEC: usize = 1_000_000_000;
+pub const USEC_PER_SEC: usize = 1_000_000;
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+pub const ITIMER_MAX_SEC: usize = u32::MAX as usize; // longer values and intervals of setitimer are rejected
 
 global_asm!(include_str!("time_handler.S"));
 
+// the wall-clock time at boot in ns, read from the RTC by init_realtime
+static BOOT_EPOCH_NS: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct TimeSpec {
+    pub tv_sec: usize,
+    pub tv_nsec: usize,
+}
+
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct TimeVal {
+    pub tv_sec: usize,
+    pub tv_usec: usize,
+}
+
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct ITimerVal {
+    pub it_interval: TimeVal,
+    pub it_value: TimeVal,
+}
+
+impl TimeSpec {
+    pub fn from_ns(ns: usize) -> Self {
+        Self { tv_sec: ns / NSEC_PER_SEC, tv_nsec: ns % NSEC_PER_SEC }
+    }
+
+    pub fn from_ticks(ticks: usize) -> Self {
+        Self::from_ns(ticks_to_ns(ticks))
+    }
+
+    pub fn to_ticks(&self) -> usize {
+        self.tv_sec.saturating_mul(CLOCK_FREQ).saturating_add(self.tv_nsec.saturating_mul(CLOCK_FREQ) / NSEC_PER_SEC)
+    }
+}
+
+impl TimeVal {
+    pub fn from_ns(ns: usize) -> Self {
+        Self { tv_sec: ns / NSEC_PER_SEC, tv_usec: ns % NSEC_PER_SEC / 1000 }
+    }
+
+    pub fn from_ticks(ticks: usize) -> Self {
+        Self { tv_sec: ticks / CLOCK_FREQ, tv_usec: ticks % CLOCK_FREQ * USEC_PER_SEC / CLOCK_FREQ }
+    }
+
+    pub fn to_ticks(&self) -> usize {
+        self.tv_sec.saturating_mul(CLOCK_FREQ).saturating_add(self.tv_usec.saturating_mul(CLOCK_FREQ) / USEC_PER_SEC)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TimerEvent {
+    Wake(usize), // wake the pid blocked in nanosleep
+    Alarm(usize), // send SIGALRM to the pid for ITIMER_REAL
+}
+
+impl TimerEvent {
+    fn pid(&self) -> usize {
+        match *self {
+            TimerEvent::Wake(pid) | TimerEvent::Alarm(pid) => pid,
+        }
+    }
+}
+
+// sorted by deadline in ticks, checked on each timer interrupt
+pub struct TimerQueue {
+    timers: BTreeMap<(usize, usize), TimerEvent>, // (deadline, seq) keeps the timers of the same deadline
+    intervals: BTreeMap<usize, usize>, // of ITIMER_REAL by pid, in ticks
+    seq: usize,
+}
+
+impl TimerQueue {
+    pub fn new() -> Self {
+        Self { timers: BTreeMap::new(), intervals: BTreeMap::new(), seq: 0 }
+    }
+
+    pub fn add(&mut self, deadline: usize, event: TimerEvent) {
+        self.seq += 1;
+        self.timers.insert((deadline, self.seq), event);
+    }
+
+    // return the deadline of the cancelled one
+    pub fn cancel(&mut self, event: TimerEvent) -> Option<usize> {
+        let key = *self.timers.iter().find(|(_, &e)| e == event)?.0;
+        self.timers.remove(&key);
+        Some(key.0)
+    }
+
+    // return the old deadline and interval, a zero deadline disarms the timer
+    pub fn set_alarm(&mut self, pid: usize, deadline: usize, interval: usize) -> (Option<usize>, usize) {
+        let old_deadline = self.cancel(TimerEvent::Alarm(pid));
+        let old_interval = self.intervals.remove(&pid).unwrap_or(0);
+        if deadline != 0 {
+            self.add(deadline, TimerEvent::Alarm(pid));
+            if interval != 0 {
+                self.intervals.insert(pid, interval);
+            }
+        }
+        (old_deadline, old_interval)
+    }
+
+    pub fn alarm(&self, pid: usize) -> (Option<usize>, usize) {
+        let deadline = self.timers.iter().find(|(_, &e)| e == TimerEvent::Alarm(pid)).map(|(key, _)| key.0);
+        (deadline, self.intervals.get(&pid).copied().unwrap_or(0))
+    }
+
+    // the timers of an exited task must not reach a later task of the same pid
+    pub fn remove_task(&mut self, pid: usize) {
+        self.timers.retain(|_, event| event.pid() != pid);
+        self.intervals.remove(&pid);
+    }
+
+    // take the expired events, a periodic alarm is added again after them,
+    // so one lagging behind fires once per call instead of catching up
+    fn expire(&mut self, now: usize) -> Vec<TimerEvent> {
+        let mut events = Vec::new();
+        let mut rearmed = Vec::new();
+        while let Some((&(deadline, seq), &event)) = self.timers.iter().next() {
+            if deadline > now {
+                break;
+            }
+            self.timers.remove(&(deadline, seq));
+            if let TimerEvent::Alarm(pid) = event {
+                if let Some(&interval) = self.intervals.get(&pid) {
+                    rearmed.push((deadline.saturating_add(interval).max(now + 1), event));
+                }
+            }
+            events.push(event);
+        }
+        for (deadline, event) in rearmed {
+            self.add(deadline, event);
+        }
+        events
+    }
+}
+
+lazy_static! {
+    pub static ref TIMER_QUEUE: SafeCellSingle<TimerQueue> = unsafe { SafeCellSingle::new(TimerQueue::new()) };
+}
+
+// called on each timer interrupt
+pub fn handle_timers() {
+    let events = TIMER_QUEUE.borrow_exclusive().expire(get_time());
+    for event in events {
+        match event {
+            TimerEvent::Wake(pid) => wake_task(pid),
+            TimerEvent::Alarm(pid) => {
+                if let Some(task) = pid2task(pid) {
+                    send_signal(&task, SIGALRM);
+                }
+            }
+        }
+    }
+}
 
 #[link_section = ".bss.stack"]
 #[no_mangle]
@@ -33,4 +204,22 @@ pub fn get_time() -> usize {
 
 pub fn get_time_ms() -> usize {
     time::read() / (CLOCK_FREQ / 1000)
-}
\ No newline at end of file
+}
+
+// the RTC is read only once, CLOCK_REALTIME follows mtime after that
+pub fn init_realtime() {
+    BOOT_EPOCH_NS.store(rtc::read_time_ns().saturating_sub(get_time_ns()), Ordering::Relaxed);
+}
+
+// ns since the epoch
+pub fn get_realtime_ns() -> usize {
+    BOOT_EPOCH_NS.load(Ordering::Relaxed) + get_time_ns()
+}
+
+pub fn get_time_ns() -> usize {
+    ticks_to_ns(get_time())
+}
+
+fn ticks_to_ns(ticks: usize) -> usize {
+    ticks * (NSEC_PER_SEC / CLOCK_FREQ) // exact for 12.5 MHz
+}
@@ -1,13 +1,20 @@
 use core::arch::{asm, global_asm};
 
-use riscv::register::{mtvec::TrapMode, scause::{self, Exception, Trap}, sip, stval, stvec};
+use riscv::register::{mtvec::TrapMode, scause::{self, Exception, Trap}, sepc, sip, stval, stvec};
 use riscv::register::scause::Interrupt;
 
+use crate::mm::area::MapPermission;
+use crate::sbi::handle_external_interrupt;
 use crate::mm::memory_set::{TRAMPOLINE, TRAP_CONTEXT};
 use crate::syscall::syscall;
-use crate::task::{current_task, current_trap_cx, current_user_token, exit_current_and_run_next, is_fixed, suspend_current_and_run_next};
-use crate::timer::get_time;
+use crate::task::coredump::dump_core;
+use crate::task::manager::task_status;
+use crate::task::signal::{catch_fault, handle_signals, SIGILL, SIGSEGV};
+use crate::task::stack::KernelStack;
+use crate::task::{current_task, current_trap_cx, current_user_satp, exit_current_and_run_next, handle_page_fault, is_fixed, suspend_current_and_run_next};
+use crate::timer::{get_time, handle_timers};
 use crate::trap::context::TrapContext;
+use crate::tty;
 
 pub(crate) mod context;
 
@@ -20,8 +27,11 @@ pub fn trap_handler() -> ! {
     let scause = scause::read();
     let stval = stval::read();
 
+    extern "C" {
+        fn __kerneltrap();
+    }
     unsafe {
-        stvec::write(trap_from_kernel as usize, TrapMode::Direct); // if trap in kernel
+        stvec::write(__kerneltrap as usize, TrapMode::Direct); // if trap in kernel
     }
     match scause.cause() {
         Trap::Exception(Exception::UserEnvCall) => {
@@ -34,14 +44,25 @@ pub fn trap_handler() -> ! {
         }
         Trap::Interrupt(Interrupt::SupervisorSoft) => {
             //println!("[timer] interrupt.");
-            let sip = sip::read().bits();
-            unsafe {
-                asm! {"csrw sip, {sip}", sip = in(reg) sip ^ 2}; // clear the interrupt status of sip
-            }
+            clear_soft_interrupt();
+            handle_timers();
             if !is_fixed() {
                 suspend_current_and_run_next();
             }
         }
+        Trap::Interrupt(Interrupt::SupervisorExternal) => {
+            handle_external_interrupt();
+            tty::poll();
+        }
+        Trap::Exception(Exception::StorePageFault) if handle_page_fault(stval, MapPermission::W) => {}
+        Trap::Exception(Exception::LoadPageFault) if handle_page_fault(stval, MapPermission::R) => {}
+        Trap::Exception(Exception::InstructionPageFault) if handle_page_fault(stval, MapPermission::X) => {}
+        Trap::Exception(Exception::StoreFault) |
+        Trap::Exception(Exception::StorePageFault) |
+        Trap::Exception(Exception::InstructionFault) |
+        Trap::Exception(Exception::InstructionPageFault) |
+        Trap::Exception(Exception::LoadFault) |
+        Trap::Exception(Exception::LoadPageFault) if catch_fault(SIGSEGV) => {}
         Trap::Exception(Exception::StoreFault) |
         Trap::Exception(Exception::StorePageFault) |
         Trap::Exception(Exception::InstructionFault) |
@@ -49,6 +70,7 @@ pub fn trap_handler() -> ! {
         Trap::Exception(Exception::LoadFault) |
         Trap::Exception(Exception::LoadPageFault) => {
             let t = current_task().unwrap().pid;
+            dump_core(SIGSEGV as u32);
             println!(
                 "[kernel] {:?} in application{}, bad addr = {:#x}, bad instruction = {:#x}, core dumped.",
                 scause.cause(), t,
@@ -58,7 +80,9 @@ pub fn trap_handler() -> ! {
             // page fault exit code
             exit_current_and_run_next(-2);
         }
+        Trap::Exception(Exception::IllegalInstruction) if catch_fault(SIGILL) => {}
         Trap::Exception(Exception::IllegalInstruction) => {
+            dump_core(SIGILL as u32);
             println!("[kernel] IllegalInstruction in application, core dumped.");
             // illegal instruction exit code
             exit_current_and_run_next(-3);
@@ -67,9 +91,32 @@ pub fn trap_handler() -> ! {
             panic!("Unsupported trap {:?}, stval = {:#x}!", scause.cause(), stval);
         }
     }
+    handle_signals();
     trap_return();
 }
 
+fn clear_soft_interrupt() {
+    let sip = sip::read().bits();
+    unsafe {
+        asm! {"csrw sip, {sip}", sip = in(reg) sip ^ 2}; // clear the interrupt status of sip
+    }
+}
+
+// interrupts are off in kernel, so the idle loop sleeps until one is pending and handles it here
+pub fn wait_for_interrupt() {
+    unsafe {
+        asm!("wfi");
+    }
+    if sip::read().sext() {
+        handle_external_interrupt();
+        tty::poll();
+    }
+    if sip::read().ssoft() {
+        clear_soft_interrupt(); // a timer tick, no task to preempt
+        handle_timers();
+    }
+}
+
 #[no_mangle]
 pub fn trap_return() -> ! {
     //println!("[trap] Begin to trap out.");
@@ -77,7 +124,7 @@ pub fn trap_return() -> ! {
         stvec::write(TRAMPOLINE as usize, TrapMode::Direct);
     }
     let trap_cx_ptr = TRAP_CONTEXT;
-    let user_satp = current_user_token();
+    let user_satp = current_user_satp();
     extern "C" {
         fn __alltraps();
         fn __restore();
@@ -96,9 +143,19 @@ pub fn trap_return() -> ! {
     panic!("[trap] Unreachable in back_to_user!");
 }
 
+// entered from __kerneltrap on the kernel trap stack, kernel_sp is where the trap happened
 #[no_mangle]
-pub fn trap_from_kernel() -> ! {
+pub fn trap_from_kernel(kernel_sp: usize) -> ! {
     let scause = scause::read();
-    panic!("{:?}", scause.cause());
-    panic!("[trap] A trap from kernel!");
+    let stval = stval::read();
+    let sepc = sepc::read();
+    if let Trap::Exception(Exception::StorePageFault | Exception::LoadPageFault) = scause.cause() {
+        if let Some(pid) = KernelStack::guard_page_owner(stval) {
+            panic!(
+                "[kernel] kernel stack overflow in pid {}, bad addr = {:#x}, sp = {:#x}, sepc = {:#x}, status = {:?}",
+                pid, stval, kernel_sp, sepc, task_status(pid)
+            );
+        }
+    }
+    panic!("[trap] A trap from kernel: {:?}, stval = {:#x}, sepc = {:#x}!", scause.cause(), stval, sepc);
 }
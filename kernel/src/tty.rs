@@ -0,0 +1,165 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use lazy_static::lazy_static;
+
+use crate::sbi::{recv, send};
+use crate::sync::safe_cell_single::SafeCellSingle;
+use crate::sync::wait_queue::WaitQueue;
+use crate::task::signal::{send_signal_to_group, SIGINT, SIGQUIT, SIGTSTP};
+
+// local modes, the same bits as linux
+pub const ISIG: u32 = 0o1;
+pub const ICANON: u32 = 0o2;
+pub const ECHO: u32 = 0o10;
+
+const ETX: u8 = 0x03; // Ctrl-C
+const SUB: u8 = 0x1a; // Ctrl-Z
+const FS: u8 = 0x1c; // Ctrl-\
+const BS: u8 = 0x08;
+const LF: u8 = 0x0a;
+const CR: u8 = 0x0d;
+const DEL: u8 = 0x7f;
+
+pub const NCCS: usize = 19;
+
+// the layout of linux struct termios for TCGETS and TCSETS, only the local modes are acted on
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct Termios {
+    pub c_iflag: u32,
+    pub c_oflag: u32,
+    pub c_cflag: u32,
+    pub c_lflag: u32,
+    pub c_line: u8,
+    pub c_cc: [u8; NCCS],
+}
+
+pub struct Tty {
+    termios: Termios,
+    pub fg_pgid: Option<usize>, // the foreground process group, which gets the signals
+    line: Vec<u8>, // being edited in canonical mode
+    input: VecDeque<u8>, // ready to be read
+    pub readers: WaitQueue, // woken when input is ready
+}
+
+impl Tty {
+    pub fn new() -> Self {
+        Self {
+            termios: Termios { c_iflag: 0, c_oflag: 0, c_cflag: 0, c_lflag: ISIG | ICANON | ECHO, c_line: 0, c_cc: [0; NCCS] },
+            fg_pgid: None,
+            line: Vec::new(),
+            input: VecDeque::new(),
+            readers: WaitQueue::new(),
+        }
+    }
+
+    pub fn termios(&self) -> Termios {
+        self.termios
+    }
+
+    // the line being edited is kept for reading when leaving canonical mode
+    pub fn set_termios(&mut self, termios: Termios) {
+        if termios.c_lflag & ICANON == 0 {
+            self.input.extend(self.line.drain(..));
+            self.wake_readers();
+        }
+        self.termios = termios;
+    }
+
+    // return the signal for the foreground group if ch generates one
+    fn receive(&mut self, ch: u8) -> Option<usize> {
+        let lflag = self.termios.c_lflag;
+        if lflag & ISIG != 0 {
+            let signo = match ch {
+                ETX => Some(SIGINT),
+                SUB => Some(SIGTSTP),
+                FS => Some(SIGQUIT),
+                _ => None,
+            };
+            if signo.is_some() {
+                if lflag & ECHO != 0 {
+                    self.echo(&[b'^', ch + b'@', LF]);
+                }
+                self.line.clear();
+                return signo;
+            }
+        }
+        if lflag & ICANON == 0 {
+            self.input.push_back(ch);
+            if lflag & ECHO != 0 {
+                self.echo(&[ch]);
+            }
+            return None;
+        }
+        match ch {
+            LF | CR => {
+                self.input.extend(self.line.drain(..));
+                self.input.push_back(LF);
+                if lflag & ECHO != 0 {
+                    self.echo(&[LF]);
+                }
+            }
+            BS | DEL => {
+                if self.line.pop().is_some() && lflag & ECHO != 0 {
+                    self.echo(&[BS, b' ', BS]); // cover the old char
+                }
+            }
+            _ => {
+                self.line.push(ch);
+                if lflag & ECHO != 0 {
+                    self.echo(&[ch]);
+                }
+            }
+        }
+        None
+    }
+
+    fn wake_readers(&mut self) {
+        if !self.input.is_empty() {
+            self.readers.wake_all();
+        }
+    }
+
+    fn echo(&self, data: &[u8]) {
+        data.iter().for_each(|&ch| send(ch));
+    }
+
+    // take at most len bytes, a read in canonical mode stops at the end of a line
+    pub fn read(&mut self, len: usize) -> Vec<u8> {
+        let mut data = Vec::new();
+        while data.len() < len {
+            match self.input.pop_front() {
+                Some(ch) => {
+                    data.push(ch);
+                    if ch == LF && self.termios.c_lflag & ICANON != 0 {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+        data
+    }
+}
+
+lazy_static! {
+    pub static ref TTY: SafeCellSingle<Tty> = unsafe { SafeCellSingle::new(Tty::new()) };
+}
+
+// move the received bytes into the line discipline, done after the UART interrupt
+pub fn poll() {
+    let mut tty = TTY.borrow_exclusive();
+    let mut signals = Vec::new();
+    while let Some(ch) = recv() {
+        if let Some(signo) = tty.receive(ch) {
+            signals.push(signo);
+        }
+    }
+    tty.wake_readers();
+    let fg_pgid = tty.fg_pgid;
+    drop(tty);
+    if let Some(pgid) = fg_pgid {
+        signals.into_iter().for_each(|signo| send_signal_to_group(pgid, signo));
+    }
+}
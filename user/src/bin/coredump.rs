@@ -0,0 +1,41 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+#[macro_use]
+extern crate user_lib;
+
+use alloc::string::String;
+use alloc::vec;
+use core::fmt::Write;
+
+use user_lib::coredump;
+
+const BYTES_PER_LINE: usize = 32;
+
+// print the last core dump in hex, `xxd -r -p` turns the lines back into the ELF core file for gdb
+#[no_mangle]
+pub fn main() -> i32 {
+    let size = match coredump(&mut []) {
+        Ok(size) => size,
+        Err(err) => {
+            println!("[coredump] no core dump: {:?}", err);
+            return -1;
+        }
+    };
+    let mut core = vec![0u8; size];
+    if let Err(err) = coredump(&mut core) {
+        println!("[coredump] failed to read the core dump: {:?}", err);
+        return -1;
+    }
+    println!("[coredump] {} bytes", size);
+    let mut hex = String::with_capacity(BYTES_PER_LINE * 2);
+    for line in core.chunks(BYTES_PER_LINE) {
+        hex.clear();
+        for byte in line {
+            write!(hex, "{:02x}", byte).unwrap();
+        }
+        println!("{}", hex);
+    }
+    0
+}
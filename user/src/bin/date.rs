@@ -0,0 +1,38 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::time::gettimeofday;
+
+const SECS_PER_DAY: usize = 86400;
+
+// the proleptic Gregorian date of days since 1970-01-01, as (year, month, day)
+fn civil_from_days(days: usize) -> (usize, usize, usize) {
+    let z = days + 719468; // days since 0000-03-01
+    let era = z / 146097;
+    let doe = z % 146097; // day of the 400-year era
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100); // day of the year starting from March
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as usize;
+    (year, month, day)
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let tv = match gettimeofday() {
+        Ok(tv) => tv,
+        Err(err) => {
+            println!("date: cannot get the time: {:?}", err);
+            return -1;
+        }
+    };
+    let (year, month, day) = civil_from_days(tv.tv_sec / SECS_PER_DAY);
+    let secs = tv.tv_sec % SECS_PER_DAY;
+    println!("{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC", year, month, day, secs / 3600, secs / 60 % 60, secs % 60);
+    0
+}
@@ -4,20 +4,22 @@
 #[macro_use]
 extern crate user_lib;
 
-use user_lib::{exec, exit, fork, wait, yield_};
+use user_lib::{exec, exit, fork, sleep, wait};
 
 #[no_mangle]
 fn main() -> i32 {
-    if fork() == 0 {
-        exec("shell\0");
+    if fork() == Ok(0) {
+        exec("shell", &["shell"]).unwrap();
     } else {
         loop {
             let mut exit_code: i32 = 0;
-            let pid = wait(-1, &mut exit_code);
-            if pid == -1 {
-                yield_();
-                continue;
-            }
+            let pid = match wait(-1, &mut exit_code) {
+                Ok(pid) => pid,
+                Err(_) => { // no child, orphans may come later
+                    sleep(100);
+                    continue;
+                }
+            };
             println!(
                 "[initproc] Released a zombie process, pid = {}, exit_code = {}",
                 pid, exit_code,
@@ -22,6 +22,7 @@ const FORK_REQUEST: usize = 1;
 const EXIT_REQUEST: usize = 2;
 const WAITPID_REQUEST: usize = 3;
 const DONE_REQUEST: usize = 4;
+const RELEASE_REQUEST: usize = 5;
 
 pub struct PidHandle(pub usize);
 
@@ -149,7 +150,10 @@ fn main() -> i32 {
                     initproc_inner.children.push(child.clone());
                 }
                 cur_inner.children.clear();
+                // the kernel sends SIGCHLD to the parent
+                let parent_pid = cur_inner.parent.as_ref().and_then(|parent| parent.upgrade()).map_or(0, |parent| parent.pid.0);
                 buffer_usize[0] = DONE_REQUEST;
+                buffer_usize[1] = parent_pid;
                 continue;
             } else if buffer_usize[0] == WAITPID_REQUEST {
                 let cur_pid = buffer_usize[1];
@@ -179,11 +183,21 @@ fn main() -> i32 {
                     buffer_usize[1] = (-2i32) as usize;
                     continue;
                 }
+            } else if buffer_usize[0] == RELEASE_REQUEST {
+                // the kernel could not create the forked child, its pid is freed without an exit
+                let pid = buffer_usize[1];
+                if let Some(proc) = processes.remove(&pid) {
+                    if let Some(parent) = proc.borrow_exclusive_inner().parent.as_ref().and_then(|parent| parent.upgrade()) {
+                        parent.borrow_exclusive_inner().children.retain(|child| child.pid.0 != pid);
+                    }
+                }
+                buffer_usize[0] = DONE_REQUEST;
+                continue;
             } else if buffer_usize[0] != NOP_REQUEST && buffer_usize[0] != DONE_REQUEST {
                 println!("[Manager] Unknown request!");
             }
         } // drop inner_borrow
-        yield_();
+        yield_(); // back to the requester, the kernel switches here only for the next request
     }
     0
 }
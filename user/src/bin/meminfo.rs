@@ -0,0 +1,30 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{meminfo, MemInfo};
+
+const PAGE_SIZE_KB: usize = 4;
+const MAX_PID: isize = 64;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut info = MemInfo::default();
+    if let Err(err) = meminfo(-1, &mut info) {
+        println!("[meminfo] failed to get memory info: {:?}", err);
+        return -1;
+    }
+    let used = info.total_frames - info.free_frames;
+    println!("total: {} frames ({} KiB)", info.total_frames, info.total_frames * PAGE_SIZE_KB);
+    println!("used:  {} frames ({} KiB)", used, used * PAGE_SIZE_KB);
+    println!("free:  {} frames ({} KiB)", info.free_frames, info.free_frames * PAGE_SIZE_KB);
+    println!("pid    frames");
+    for pid in 0..MAX_PID {
+        if meminfo(pid, &mut info).is_ok() {
+            println!("{:<6} {}", pid, info.task_frames);
+        }
+    }
+    0
+}
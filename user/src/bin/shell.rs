@@ -8,83 +8,81 @@ extern crate user_lib;
 use alloc::string::String;
 use alloc::vec::Vec;
 
-use user_lib::{exec, fork, kill, read, read_without_block, wait, waitpid, yield_};
+use user_lib::{exec, fork, getpid, read, setpgid, stop_signal, waitpid, SysError, WUNTRACED};
+use user_lib::signal::{sigaction, SigAction, SIGINT, SIGQUIT, SIGTSTP};
+use user_lib::tty::tcsetpgrp;
 
 const STDIN: usize = 0;
-const SIGKILL: u8 = 9;
-const LF: u8 = 0x0au8;
-const CR: u8 = 0x0du8;
-const BS: u8 = 0x08u8;
-const DEL: u8 = 0x7fu8;
-const ETX: u8 = 0x03u8;
+
+// caught rather than ignored, so the children get the default actions back after exec
+extern "C" fn interrupted(_signo: usize) {}
 
 #[no_mangle]
 fn main() -> i32 {
+    for signo in [SIGINT, SIGQUIT, SIGTSTP] {
+        sigaction(signo, Some(&SigAction::new(interrupted)), None).unwrap();
+    }
+    let shell_pid = getpid() as usize;
+    setpgid(0, 0).unwrap();
+    tcsetpgrp(STDIN, shell_pid).unwrap();
     println!("\x1b[32m[shell] Begin user shell.\n>> \x1b[0m");
-    let mut buf = [0u8; 1];
+    let mut buf = [0u8; 256];
     let mut cmd: String = String::new();
     loop {
-        read(STDIN, buf.as_mut());
-        match buf[0] {
-            LF | CR => {
-                print!("\n");
-                if !cmd.is_empty() {
-                    cmd.push('\0');
-                    let pid = fork();
-                    if pid == 0 {
-                        if exec(cmd.as_str()) == -1 {
-                            println!("Error when executing!");
-                            return -4;
-                        }
-                        unreachable!();
-                    } else {
-                        let mut exit_code: i32 = 0;
-                        let mut buf = [0u8; 1];
-                        let mut cnt: usize = 0;
-                        loop {
-                            cnt += 1;
-                            match waitpid(pid as usize, &mut exit_code) {
-                                -2 => {
-                                    yield_();
-                                }
-                                exit_pid => { // -1 or a real pid
-                                    assert_eq!(pid, exit_pid);
-                                    println!("[shell] Process {} exited with code {}", pid, exit_code);
-                                    break;
+        match read(STDIN, &mut buf) {
+            Ok(len) => cmd.push_str(&String::from_utf8_lossy(&buf[..len])),
+            Err(SysError::EINTR) => { // the line is dropped by the terminal
+                cmd.clear();
+                print!("\x1b[32m>> \x1b[0m");
+                continue;
+            }
+            Err(err) => {
+                println!("[shell] Failed to read: {:?}", err);
+                return -1;
+            }
+        }
+        if !cmd.ends_with('\n') {
+            continue; // the line is longer than buf
+        }
+        let args: Vec<&str> = cmd.split_whitespace().collect();
+        if !args.is_empty() {
+            match fork() {
+                Ok(0) => {
+                    setpgid(0, 0).unwrap();
+                    if let Err(err) = exec(args[0], &args) {
+                        println!("Error when executing: {:?}", err);
+                        return -4;
+                    }
+                    unreachable!();
+                }
+                Ok(pid) => {
+                    // also done by the child, whichever runs first
+                    setpgid(pid, 0).ok();
+                    tcsetpgrp(STDIN, pid).ok();
+                    let mut exit_code: i32 = 0;
+                    loop {
+                        match waitpid(pid, &mut exit_code, WUNTRACED) {
+                            Err(SysError::EINTR) => {}
+                            Ok(exit_pid) => {
+                                assert_eq!(pid, exit_pid);
+                                match stop_signal(exit_code) {
+                                    Some(signo) => println!("[shell] Process {} is stopped by signal {}", pid, signo),
+                                    None => println!("[shell] Process {} exited with code {}", pid, exit_code),
                                 }
+                                break;
                             }
-                            if cnt % 10 == 0 {
-                                read_without_block(STDIN, buf.as_mut());
-                                if buf[0] == ETX {
-                                    if kill(pid, SIGKILL) == 0 {
-                                        let kill_pid = waitpid(pid as usize, &mut exit_code);
-                                        assert_eq!(pid, kill_pid);
-                                        assert_eq!(exit_code, SIGKILL as i32);
-                                        println!("[shell] Process {} is killed successfully.", pid);
-                                    } else {
-                                        println!("[shell] Process {} cannot be killed.", pid);
-                                    }
-                                    break;
-                                }
+                            Err(err) => {
+                                println!("[shell] Failed to wait process {}: {:?}", pid, err);
+                                break;
                             }
                         }
                     }
-                    cmd.clear();
+                    tcsetpgrp(STDIN, shell_pid).unwrap();
                 }
-                print!("\x1b[32m>> \x1b[0m");
-            }
-            BS | DEL => {
-                if !cmd.is_empty() {
-                    print!("{}", BS as char); // control the cursor
-                    print!(" "); // cover the old char
-                    print!("{}", BS as char); // control the cursor again
-                    cmd.pop();
-                }
-            }
-            _ => {
-                print!("{}", buf[0] as char);
-                cmd.push(buf[0] as char);
+                Err(err) => println!("[shell] Failed to fork: {:?}", err),
             }
         }
+        cmd.clear();
+        print!("\x1b[32m>> \x1b[0m");
     }
-}
\ No newline at end of file
+}
@@ -2,10 +2,14 @@ use core::alloc::{GlobalAlloc, Layout};
 use core::cmp::{max, min};
 use core::ptr::null_mut;
 
+use crate::sbrk;
+
 pub const BLOCK_UNIT_SIZE: usize = 0x10;
 pub const BLOCK_LEVEL: usize = 11;
 pub const TABLE_SIZE: usize = 1024;
-const USER_HEAP_SIZE: usize = 0x4000;
+pub const ARENA_SIZE: usize = BLOCK_UNIT_SIZE << (BLOCK_LEVEL - 1);
+pub const MAX_ARENA: usize = 64;
+const PAGE_SIZE: usize = 0x1000;
 
 #[derive(Copy, Clone)]
 struct LinkNode {
@@ -28,6 +32,15 @@ pub struct Allocator {
     heap_beg_addr: usize,
 }
 
+// the heap is a list of buddy arenas taken from the program break, blocks larger than
+// an arena are taken from the program break directly
+pub struct Heap {
+    arenas: [Allocator; MAX_ARENA],
+    arena_num: usize,
+    // free list of large blocks, each one begins with (size, next)
+    big_free: usize,
+}
+
 pub struct AllocatorWrap {
     pub allocator: usize,
 }
@@ -44,7 +57,14 @@ impl Allocator {
         allocator
     }
 
+    const ZERO: Allocator = Allocator {
+        free_head: [0; BLOCK_LEVEL],
+        link_table: [LinkNode { prev: 0, next: 0, level: 0, free: false }; TABLE_SIZE],
+        heap_beg_addr: 0,
+    };
+
     pub fn init(&mut self, _heap_beg_addr: usize) {
+        self.free_head = [-1i16; BLOCK_LEVEL];
         self.heap_beg_addr = _heap_beg_addr;
         self.push(0, BLOCK_LEVEL - 1);
     }
@@ -130,30 +150,113 @@ impl Allocator {
     }
 }
 
+impl Heap {
+    pub const fn empty() -> Self {
+        Heap {
+            arenas: [Allocator::ZERO; MAX_ARENA], // all zero, stays in .bss
+            arena_num: 0,
+            big_free: 0,
+        }
+    }
+
+    fn alloc(&mut self, level: usize) -> *mut u8 {
+        for arena in self.arenas[..self.arena_num].iter_mut() {
+            let ptr = arena.split(level);
+            if !ptr.is_null() {
+                return ptr;
+            }
+        }
+        if self.arena_num == MAX_ARENA {
+            return null_mut();
+        }
+        let arena_beg = match sbrk(ARENA_SIZE as isize) {
+            Ok(arena_beg) => arena_beg,
+            Err(_) => return null_mut(),
+        };
+        self.arenas[self.arena_num].init(arena_beg);
+        self.arena_num += 1;
+        self.arenas[self.arena_num - 1].split(level)
+    }
+
+    fn dealloc(&mut self, addr: usize, level: usize) {
+        match self.arenas[..self.arena_num].iter_mut()
+            .find(|arena| arena.heap_beg_addr <= addr && addr < arena.heap_beg_addr + ARENA_SIZE) {
+            Some(arena) => arena.merge(addr, level),
+            None => panic!("[user]: Invalid address to dealloc."),
+        }
+    }
+
+    fn alloc_big(&mut self, size: usize) -> *mut u8 {
+        let mut prev: *mut usize = &mut self.big_free;
+        unsafe {
+            while *prev != 0 { // first fit
+                let block = *prev as *mut usize;
+                if *block == size {
+                    *prev = *block.add(1);
+                    return block as *mut u8;
+                }
+                if *block > size { // the tail stays free, dealloc only knows the requested size
+                    let rest = (block as usize + size) as *mut usize;
+                    *rest = *block - size;
+                    *rest.add(1) = *block.add(1);
+                    *prev = rest as usize;
+                    return block as *mut u8;
+                }
+                prev = block.add(1);
+            }
+        }
+        match sbrk(size as isize) {
+            Ok(block_beg) => block_beg as *mut u8,
+            Err(_) => null_mut(),
+        }
+    }
+
+    fn dealloc_big(&mut self, addr: usize, size: usize) {
+        let block = addr as *mut usize;
+        unsafe {
+            *block = size;
+            *block.add(1) = self.big_free;
+        }
+        self.big_free = addr;
+    }
+}
+
 impl AllocatorWrap {
     pub const fn empty() -> Self {
         AllocatorWrap { allocator: 0 }
     }
 
-    pub unsafe fn init(&mut self, allocator: &mut Allocator) {
-        self.allocator = allocator as *mut Allocator as usize;
+    pub unsafe fn init(&mut self, heap: &mut Heap) {
+        self.allocator = heap as *mut Heap as usize;
     }
 }
 
 unsafe impl GlobalAlloc for AllocatorWrap {
     unsafe fn alloc(&self, _layout: Layout) -> *mut u8 {
         let size = max(max(_layout.size().next_power_of_two(), BLOCK_UNIT_SIZE), _layout.align());
-        let alloctor = &mut *(self.allocator as *mut Allocator);
-        alloctor.split((size / BLOCK_UNIT_SIZE).trailing_zeros() as usize)
+        let heap = &mut *(self.allocator as *mut Heap);
+        if size > ARENA_SIZE {
+            if _layout.align() > PAGE_SIZE {
+                return null_mut(); // large blocks are only page aligned
+            }
+            heap.alloc_big(big_size(_layout))
+        } else {
+            heap.alloc((size / BLOCK_UNIT_SIZE).trailing_zeros() as usize)
+        }
     }
 
     unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
-        let alloctor = &mut *(self.allocator as *mut Allocator);
-        if alloctor.heap_beg_addr > _ptr as usize ||
-            alloctor.heap_beg_addr + USER_HEAP_SIZE <= _ptr as usize {
-            panic!("[user]: Invalid address to dealloc.");
-        }
         let size = max(max(_layout.size().next_power_of_two(), BLOCK_UNIT_SIZE), _layout.align());
-        alloctor.merge(_ptr as usize, (size / BLOCK_UNIT_SIZE).trailing_zeros() as usize);
+        let heap = &mut *(self.allocator as *mut Heap);
+        if size > ARENA_SIZE {
+            heap.dealloc_big(_ptr as usize, big_size(_layout));
+        } else {
+            heap.dealloc(_ptr as usize, (size / BLOCK_UNIT_SIZE).trailing_zeros() as usize);
+        }
     }
 }
+
+// large blocks are whole pages, so the program break stays page aligned
+fn big_size(layout: Layout) -> usize {
+    (layout.size() + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE
+}
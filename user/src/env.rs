@@ -0,0 +1,62 @@
+use core::ptr::null;
+
+// set by _start from the System V stack layout: argc, argv, NULL, envp, NULL, auxv
+static mut ARGC: usize = 0;
+static mut ARGV: *const *const u8 = null();
+static mut ENVP: *const *const u8 = null();
+
+// iterator over a NULL-terminated array of strings, e.g. argv
+#[derive(Clone)]
+pub struct Args {
+    ptr: *const *const u8,
+}
+
+impl Iterator for Args {
+    type Item = &'static str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        unsafe {
+            if self.ptr.is_null() || (*self.ptr).is_null() {
+                return None;
+            }
+            let s = *self.ptr;
+            self.ptr = self.ptr.add(1);
+            let mut len = 0;
+            while *s.add(len) != 0 {
+                len += 1;
+            }
+            // kernel only passes utf-8 strings
+            Some(core::str::from_utf8_unchecked(core::slice::from_raw_parts(s, len)))
+        }
+    }
+}
+
+pub(crate) fn init(sp: *const usize) {
+    unsafe {
+        ARGC = *sp;
+        ARGV = sp.add(1) as *const *const u8;
+        ENVP = ARGV.add(ARGC + 1);
+    }
+}
+
+pub fn argc() -> usize {
+    unsafe { ARGC }
+}
+
+// argv[0] is the program name by convention
+pub fn args() -> Args {
+    Args { ptr: unsafe { ARGV } }
+}
+
+// raw "KEY=VALUE" strings of envp
+pub fn environ() -> Args {
+    Args { ptr: unsafe { ENVP } }
+}
+
+pub fn vars() -> impl Iterator<Item = (&'static str, &'static str)> {
+    environ().map(|s| s.split_once('=').unwrap_or((s, "")))
+}
+
+pub fn var(key: &str) -> Option<&'static str> {
+    vars().find(|(k, _)| *k == key).map(|(_, v)| v)
+}
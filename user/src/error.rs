@@ -0,0 +1,49 @@
+// errno values as in Linux, the same as kernel's SysError
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[repr(isize)]
+pub enum SysError {
+    EPERM = 1,
+    ENOENT = 2,
+    ESRCH = 3,
+    EINTR = 4,
+    E2BIG = 7,
+    ENOEXEC = 8,
+    EBADF = 9,
+    ECHILD = 10,
+    EAGAIN = 11,
+    ENOMEM = 12,
+    EFAULT = 14,
+    EINVAL = 22,
+    ENOTTY = 25,
+    ENAMETOOLONG = 36,
+    ENOSYS = 38,
+}
+
+pub type SysResult<T = usize> = Result<T, SysError>;
+
+impl SysError {
+    pub fn from_errno(errno: isize) -> Self {
+        match errno {
+            1 => SysError::EPERM,
+            2 => SysError::ENOENT,
+            3 => SysError::ESRCH,
+            4 => SysError::EINTR,
+            7 => SysError::E2BIG,
+            8 => SysError::ENOEXEC,
+            9 => SysError::EBADF,
+            10 => SysError::ECHILD,
+            11 => SysError::EAGAIN,
+            12 => SysError::ENOMEM,
+            14 => SysError::EFAULT,
+            25 => SysError::ENOTTY,
+            36 => SysError::ENAMETOOLONG,
+            38 => SysError::ENOSYS,
+            _ => SysError::EINVAL,
+        }
+    }
+}
+
+// a negative return value of syscall is -errno
+pub fn from_ret(ret: isize) -> SysResult {
+    if ret < 0 { Err(SysError::from_errno(-ret)) } else { Ok(ret as usize) }
+}
@@ -3,31 +3,73 @@
 #![feature(panic_info_message)]
 #![feature(alloc_error_handler)]
 
-use crate::buddy::{Allocator, AllocatorWrap};
-use crate::syscall::{sys_exec, sys_exit, sys_fork, sys_get_time, sys_getpid, sys_kill, sys_read, sys_waitpid, sys_write, sys_yield};
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::arch::global_asm;
+use core::ptr::null;
+
+use crate::buddy::{AllocatorWrap, Heap};
+use crate::error::from_ret;
+use crate::syscall::{sys_brk, sys_coredump, sys_exec, sys_exit, sys_fork, sys_getpgid, sys_getpid, sys_kill, sys_meminfo, sys_mmap, sys_mprotect, sys_munmap, sys_read, sys_setpgid, sys_shmat, sys_shmctl, sys_shmdt, sys_shmget, sys_waitpid, sys_write, sys_yield};
+use crate::time::{clock_gettime, nanosleep, TimeSpec, CLOCK_MONOTONIC};
 
 mod buddy;
 mod syscall;
+pub mod error;
 mod lang_items;
 pub mod console;
+pub mod env;
+pub mod signal;
 pub mod sync;
+pub mod time;
+pub mod tty;
+
+pub use crate::error::{SysError, SysResult};
+
+pub const PROT_NONE: usize = 0;
+pub const PROT_READ: usize = 1;
+pub const PROT_WRITE: usize = 2;
+pub const PROT_EXEC: usize = 4;
+pub const MAP_PRIVATE: usize = 0x02;
+pub const MAP_ANONYMOUS: usize = 0x20;
+pub const IPC_PRIVATE: usize = 0;
+pub const IPC_CREAT: usize = 0o1000;
+pub const IPC_RMID: usize = 0;
+pub const SHM_RDONLY: usize = 0o10000;
+pub const WNOHANG: usize = 1;
+pub const WUNTRACED: usize = 2;
+
+// counted in frames (pages)
+#[repr(C)]
+#[derive(Default)]
+pub struct MemInfo {
+    pub total_frames: usize,
+    pub free_frames: usize,
+    pub task_frames: usize,
+}
 
-const USER_HEAP_SIZE: usize = 0x4000;
-
-static mut HEAP_SPACE: [u8; USER_HEAP_SIZE] = [0; USER_HEAP_SIZE];
-
-static mut INNER_ALLOCATOR: Allocator = Allocator::empty();
+static mut INNER_ALLOCATOR: Heap = Heap::empty();
 
 #[global_allocator]
 static mut HEAP_ALLOCATOR: AllocatorWrap = AllocatorWrap::empty();
 
+// sp points to argc when entering, take it before any prologue touches the stack
+global_asm!(
+    ".section .text.entry",
+    ".globl _start",
+    "_start:",
+    "    mv a0, sp",
+    "    call start_main",
+);
+
 #[no_mangle]
-#[link_section = ".text.entry"]
-pub extern "C" fn _start() -> ! {
+extern "C" fn start_main(sp: *const usize) -> ! {
     // don't need to clear .bss, since it's done when loading ELF
+    env::init(sp);
     init_heap();
     exit(main());
-    panic!("unreachable after sys_exit!");
 }
 
 #[linkage = "weak"]
@@ -38,67 +80,151 @@ fn main() -> i32 {
 
 pub fn init_heap() {
     unsafe {
-        INNER_ALLOCATOR.init(HEAP_SPACE.as_ptr() as usize);
-        HEAP_ALLOCATOR.allocator = &mut INNER_ALLOCATOR as *mut Allocator as usize;
+        HEAP_ALLOCATOR.allocator = &mut INNER_ALLOCATOR as *mut Heap as usize;
     }
 }
 
-pub fn write(fd: usize, buf: &[u8]) -> isize {
-    sys_write(fd, buf)
+pub fn write(fd: usize, buf: &[u8]) -> SysResult {
+    from_ret(sys_write(fd, buf))
 }
 
-pub fn read(fd: usize, buf: &mut [u8]) -> isize {
-    sys_read(fd, buf, buf.len())
+pub fn read(fd: usize, buf: &mut [u8]) -> SysResult {
+    from_ret(sys_read(fd, buf, buf.len()))
 }
 
-pub fn read_without_block(fd: usize, buf: &mut [u8]) -> isize {
-    sys_read(fd, buf, 0)
+pub fn read_without_block(fd: usize, buf: &mut [u8]) -> SysResult {
+    from_ret(sys_read(fd, buf, 0))
 }
 
 pub fn exit(exit_code: i32) -> ! {
     sys_exit(exit_code);
 }
 
-pub fn fork() -> isize {
-    sys_fork()
+// Ok(0) in the child, Ok(child pid) in the parent
+pub fn fork() -> SysResult {
+    from_ret(sys_fork())
 }
 
-pub fn exec(path: &str) -> isize {
-    sys_exec(path)
+// run path with args as argv, never returns on success, envp is inherited
+pub fn exec(path: &str, args: &[&str]) -> SysResult {
+    let envs: Vec<&str> = env::environ().collect();
+    execve(path, args, &envs)
 }
 
-pub fn wait(pid: isize, exit_code: &mut i32) -> isize {
-    loop {
-        match sys_waitpid(pid as isize, exit_code as *mut _) {
-            -2 => { yield_(); }
-            // -1 or a real pid
-            exit_pid => return exit_pid,
-        }
-    }
+pub fn execve(path: &str, args: &[&str], envs: &[&str]) -> SysResult {
+    let path = c_string(path);
+    let args: Vec<String> = args.iter().map(|arg| c_string(arg)).collect();
+    let envs: Vec<String> = envs.iter().map(|env| c_string(env)).collect();
+    let argv: Vec<*const u8> = args.iter().map(|arg| arg.as_ptr()).chain([null()]).collect();
+    let envp: Vec<*const u8> = envs.iter().map(|env| env.as_ptr()).chain([null()]).collect();
+    from_ret(sys_exec(&path, &argv, &envp))
+}
+
+fn c_string(s: &str) -> String {
+    let mut s = String::from(s);
+    s.push('\0');
+    s
+}
+
+// pid = -1 waits for any child, block until one exits
+pub fn wait(pid: isize, exit_code: &mut i32) -> SysResult {
+    from_ret(sys_waitpid(pid, exit_code as *mut _, 0))
+}
+
+// block until the child exits, or EAGAIN with WNOHANG, with WUNTRACED a stop of the child is also reported
+pub fn waitpid(pid: usize, exit_code: &mut i32, options: usize) -> SysResult {
+    from_ret(sys_waitpid(pid as isize, exit_code as *mut _, options))
 }
 
-pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
-    sys_waitpid(pid as isize, exit_code as *mut _)
+// the signal which stopped the child, if exit_code is reported for a stop
+pub fn stop_signal(exit_code: i32) -> Option<usize> {
+    if exit_code & 0xff == 0x7f && exit_code >> 8 != 0 { Some((exit_code >> 8) as usize) } else { None }
 }
 
 pub fn yield_() -> isize {
     sys_yield()
 }
 
-pub fn kill(pid: isize, signal: u8) -> isize {
-    sys_kill(pid, signal)
+// signo = 0 only checks that the process exists
+pub fn kill(pid: isize, signo: usize) -> SysResult {
+    from_ret(sys_kill(pid, signo))
+}
+
+// pid = 0 stands for the calling process, pgid = 0 makes a new group led by pid
+pub fn setpgid(pid: usize, pgid: usize) -> SysResult {
+    from_ret(sys_setpgid(pid, pgid))
+}
+
+pub fn getpgid(pid: usize) -> SysResult {
+    from_ret(sys_getpgid(pid))
 }
 
 pub fn get_time() -> isize {
-    sys_get_time()
+    let tp = clock_gettime(CLOCK_MONOTONIC).unwrap();
+    (tp.tv_sec * 1000 + tp.tv_nsec / 1_000_000) as isize // ms since boot
 }
 pub fn getpid() -> isize {
     sys_getpid()
 }
 
+// return early if a signal is caught
 pub fn sleep(period_ms: usize) {
-    let start = sys_get_time();
-    while sys_get_time() < start + period_ms as isize {
-        sys_yield();
+    let req = TimeSpec { tv_sec: period_ms / 1000, tv_nsec: period_ms % 1000 * 1_000_000 };
+    nanosleep(&req, None).ok();
+}
+
+// return the new program break, which stays unchanged on failure
+pub fn brk(addr: usize) -> usize {
+    sys_brk(addr) as usize
+}
+
+// move the program break by increment, return the old one
+pub fn sbrk(increment: isize) -> SysResult {
+    let old_brk = sys_brk(0);
+    let new_brk = old_brk + increment;
+    if sys_brk(new_brk as usize) != new_brk {
+        return Err(SysError::ENOMEM);
     }
-}
\ No newline at end of file
+    Ok(old_brk as usize)
+}
+
+// map anonymous memory, addr = 0 lets kernel pick the address
+pub fn mmap(addr: usize, len: usize, prot: usize, flags: usize) -> SysResult {
+    from_ret(sys_mmap(addr, len, prot, flags))
+}
+
+pub fn munmap(addr: usize, len: usize) -> SysResult {
+    from_ret(sys_munmap(addr, len))
+}
+
+pub fn mprotect(addr: usize, len: usize, prot: usize) -> SysResult {
+    from_ret(sys_mprotect(addr, len, prot))
+}
+
+// get the id of the segment of key, IPC_PRIVATE always creates a new one
+pub fn shmget(key: usize, size: usize, flags: usize) -> SysResult {
+    from_ret(sys_shmget(key, size, flags))
+}
+
+// attach the segment, addr = 0 lets kernel pick the address
+pub fn shmat(id: usize, addr: usize, flags: usize) -> SysResult {
+    from_ret(sys_shmat(id, addr, flags))
+}
+
+pub fn shmdt(addr: usize) -> SysResult {
+    from_ret(sys_shmdt(addr))
+}
+
+pub fn shmctl(id: usize, cmd: usize) -> SysResult {
+    from_ret(sys_shmctl(id, cmd))
+}
+
+// pid = -1 for the calling process
+pub fn meminfo(pid: isize, info: &mut MemInfo) -> SysResult {
+    from_ret(sys_meminfo(pid, info as *mut MemInfo))
+}
+
+// copy the last core dump as far as buf holds, return its full size
+pub fn coredump(buf: &mut [u8]) -> SysResult {
+    from_ret(sys_coredump(buf))
+}
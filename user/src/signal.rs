@@ -0,0 +1,74 @@
+use core::arch::global_asm;
+use core::ptr::{null, null_mut};
+
+use crate::error::{from_ret, SysResult};
+use crate::syscall::{sys_sigaction, sys_sigprocmask};
+
+pub const SIGINT: usize = 2;
+pub const SIGQUIT: usize = 3;
+pub const SIGILL: usize = 4;
+pub const SIGKILL: usize = 9;
+pub const SIGSEGV: usize = 11;
+pub const SIGALRM: usize = 14;
+pub const SIGTERM: usize = 15;
+pub const SIGCHLD: usize = 17;
+pub const SIGCONT: usize = 18;
+pub const SIGSTOP: usize = 19;
+pub const SIGTSTP: usize = 20;
+
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+pub const SA_NODEFER: usize = 0x4000_0000;
+pub const SIG_BLOCK: usize = 0;
+pub const SIG_UNBLOCK: usize = 1;
+pub const SIG_SETMASK: usize = 2;
+
+// the same layout as kernel's
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct SigAction {
+    pub handler: usize, // SIG_DFL, SIG_IGN, or an extern "C" fn(signo: usize)
+    pub mask: u32, // blocked during the handler, besides the signal itself
+    pub flags: usize,
+    pub restorer: usize,
+}
+
+impl SigAction {
+    pub fn new(handler: extern "C" fn(usize)) -> Self {
+        Self { handler: handler as usize, ..Self::default() }
+    }
+}
+
+// a handler returns here, with sp at the signal frame
+global_asm!(
+    ".section .text",
+    ".globl __sigreturn",
+    "__sigreturn:",
+    "    li a7, 139",
+    "    ecall",
+);
+
+extern "C" {
+    fn __sigreturn();
+}
+
+// the restorer is filled in, so a handler simply returns
+pub fn sigaction(signo: usize, act: Option<&SigAction>, old_act: Option<&mut SigAction>) -> SysResult {
+    let act = act.map(|act| SigAction { restorer: __sigreturn as usize, ..*act });
+    let act_ptr = act.as_ref().map_or(null(), |act| act as *const SigAction);
+    let old_act_ptr = old_act.map_or(null_mut(), |old_act| old_act as *mut SigAction);
+    from_ret(sys_sigaction(signo, act_ptr, old_act_ptr))
+}
+
+// set the handler, SIG_DFL or SIG_IGN of signo, return the old one
+pub fn signal(signo: usize, handler: usize) -> SysResult {
+    let mut old_act = SigAction::default();
+    sigaction(signo, Some(&SigAction { handler, ..SigAction::default() }), Some(&mut old_act))?;
+    Ok(old_act.handler)
+}
+
+pub fn sigprocmask(how: usize, set: Option<u32>, old_set: Option<&mut u32>) -> SysResult {
+    let set_ptr = set.as_ref().map_or(null(), |set| set as *const u32);
+    let old_set_ptr = old_set.map_or(null_mut(), |old_set| old_set as *mut u32);
+    from_ret(sys_sigprocmask(how, set_ptr, old_set_ptr))
+}
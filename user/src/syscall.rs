@@ -1,15 +1,38 @@
 use core::arch::asm;
 
+use crate::MemInfo;
+use crate::signal::SigAction;
+use crate::time::{ITimerVal, TimeSpec, TimeVal};
+
+const SYSCALL_IOCTL: usize = 29;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_NANOSLEEP: usize = 101;
+const SYSCALL_GETITIMER: usize = 102;
+const SYSCALL_SETITIMER: usize = 103;
+const SYSCALL_CLOCK_GETTIME: usize = 113;
 const SYSCALL_YIELD: usize = 124;
-const SYSCALL_GET_TIME: usize = 169;
+const SYSCALL_SETPGID: usize = 154;
+const SYSCALL_GETPGID: usize = 155;
+const SYSCALL_GETTIMEOFDAY: usize = 169;
 const SYSCALL_GETPID: usize = 172;
+const SYSCALL_SHMGET: usize = 194;
+const SYSCALL_SHMCTL: usize = 195;
+const SYSCALL_SHMAT: usize = 196;
+const SYSCALL_SHMDT: usize = 197;
+const SYSCALL_BRK: usize = 214;
+const SYSCALL_MUNMAP: usize = 215;
+const SYSCALL_MMAP: usize = 222;
+const SYSCALL_MPROTECT: usize = 226;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
 const SYSCALL_WAITPID: usize = 260;
 const SYSCALL_KILL: usize = 129;
+const SYSCALL_SIGACTION: usize = 134;
+const SYSCALL_SIGPROCMASK: usize = 135;
+const SYSCALL_MEMINFO: usize = 500;
+const SYSCALL_COREDUMP: usize = 501;
 
 fn syscall(id: usize, args: [usize; 7]) -> isize {
     let mut ret: isize;
@@ -47,26 +70,102 @@ pub fn sys_fork() -> isize {
     syscall(SYSCALL_FORK, [0, 0, 0, 0, 0, 0, 0])
 }
 
-pub fn sys_exec(path: &str) -> isize {
-    syscall(SYSCALL_EXEC, [path.as_ptr() as usize, 0, 0, 0, 0, 0, 0])
+pub fn sys_exec(path: &str, argv: &[*const u8], envp: &[*const u8]) -> isize {
+    syscall(SYSCALL_EXEC, [path.as_ptr() as usize, argv.as_ptr() as usize, envp.as_ptr() as usize, 0, 0, 0, 0])
+}
+
+pub fn sys_waitpid(pid: isize, exit_code: *mut i32, options: usize) -> isize {
+    syscall(SYSCALL_WAITPID, [pid as usize, exit_code as usize, options, 0, 0, 0, 0])
+}
+
+pub fn sys_nanosleep(req: &TimeSpec, rem: *mut TimeSpec) -> isize {
+    syscall(SYSCALL_NANOSLEEP, [req as *const TimeSpec as usize, rem as usize, 0, 0, 0, 0, 0])
 }
 
-pub fn sys_waitpid(pid: isize, exit_code: *mut i32) -> isize {
-    syscall(SYSCALL_WAITPID, [pid as usize, exit_code as usize, 0, 0, 0, 0, 0])
+pub fn sys_getitimer(which: usize, curr_value: &mut ITimerVal) -> isize {
+    syscall(SYSCALL_GETITIMER, [which, curr_value as *mut ITimerVal as usize, 0, 0, 0, 0, 0])
+}
+
+pub fn sys_setitimer(which: usize, new_value: &ITimerVal, old_value: &mut ITimerVal) -> isize {
+    syscall(SYSCALL_SETITIMER, [which, new_value as *const ITimerVal as usize, old_value as *mut ITimerVal as usize, 0, 0, 0, 0])
+}
+
+pub fn sys_clock_gettime(clock_id: usize, tp: &mut TimeSpec) -> isize {
+    syscall(SYSCALL_CLOCK_GETTIME, [clock_id, tp as *mut TimeSpec as usize, 0, 0, 0, 0, 0])
 }
 
 pub fn sys_yield() -> isize {
     syscall(SYSCALL_YIELD, [0, 0, 0, 0, 0, 0, 0])
 }
 
-pub fn sys_kill(pid: isize, signal: u8) -> isize {
-    syscall(SYSCALL_KILL, [pid as usize, signal as usize, 0, 0, 0, 0, 0])
+pub fn sys_kill(pid: isize, signo: usize) -> isize {
+    syscall(SYSCALL_KILL, [pid as usize, signo, 0, 0, 0, 0, 0])
+}
+
+pub fn sys_sigaction(signo: usize, act: *const SigAction, old_act: *mut SigAction) -> isize {
+    syscall(SYSCALL_SIGACTION, [signo, act as usize, old_act as usize, 0, 0, 0, 0])
+}
+
+pub fn sys_sigprocmask(how: usize, set: *const u32, old_set: *mut u32) -> isize {
+    syscall(SYSCALL_SIGPROCMASK, [how, set as usize, old_set as usize, 0, 0, 0, 0])
+}
+
+pub fn sys_ioctl(fd: usize, cmd: usize, arg: usize) -> isize {
+    syscall(SYSCALL_IOCTL, [fd, cmd, arg, 0, 0, 0, 0])
+}
+
+pub fn sys_setpgid(pid: usize, pgid: usize) -> isize {
+    syscall(SYSCALL_SETPGID, [pid, pgid, 0, 0, 0, 0, 0])
+}
+
+pub fn sys_getpgid(pid: usize) -> isize {
+    syscall(SYSCALL_GETPGID, [pid, 0, 0, 0, 0, 0, 0])
 }
 
 pub fn sys_getpid() -> isize {
     syscall(SYSCALL_GETPID, [0, 0, 0, 0, 0, 0, 0])
 }
 
-pub fn sys_get_time() -> isize {
-    syscall(SYSCALL_GET_TIME, [0, 0, 0, 0, 0, 0, 0])
-}
\ No newline at end of file
+pub fn sys_gettimeofday(tv: &mut TimeVal) -> isize {
+    syscall(SYSCALL_GETTIMEOFDAY, [tv as *mut TimeVal as usize, 0, 0, 0, 0, 0, 0])
+}
+
+pub fn sys_brk(addr: usize) -> isize {
+    syscall(SYSCALL_BRK, [addr, 0, 0, 0, 0, 0, 0])
+}
+
+pub fn sys_mmap(addr: usize, len: usize, prot: usize, flags: usize) -> isize {
+    syscall(SYSCALL_MMAP, [addr, len, prot, flags, 0, 0, 0])
+}
+
+pub fn sys_munmap(addr: usize, len: usize) -> isize {
+    syscall(SYSCALL_MUNMAP, [addr, len, 0, 0, 0, 0, 0])
+}
+
+pub fn sys_mprotect(addr: usize, len: usize, prot: usize) -> isize {
+    syscall(SYSCALL_MPROTECT, [addr, len, prot, 0, 0, 0, 0])
+}
+
+pub fn sys_shmget(key: usize, size: usize, flags: usize) -> isize {
+    syscall(SYSCALL_SHMGET, [key, size, flags, 0, 0, 0, 0])
+}
+
+pub fn sys_shmat(id: usize, addr: usize, flags: usize) -> isize {
+    syscall(SYSCALL_SHMAT, [id, addr, flags, 0, 0, 0, 0])
+}
+
+pub fn sys_shmdt(addr: usize) -> isize {
+    syscall(SYSCALL_SHMDT, [addr, 0, 0, 0, 0, 0, 0])
+}
+
+pub fn sys_shmctl(id: usize, cmd: usize) -> isize {
+    syscall(SYSCALL_SHMCTL, [id, cmd, 0, 0, 0, 0, 0])
+}
+
+pub fn sys_meminfo(pid: isize, info: *mut MemInfo) -> isize {
+    syscall(SYSCALL_MEMINFO, [pid as usize, info as usize, 0, 0, 0, 0, 0])
+}
+
+pub fn sys_coredump(buf: &mut [u8]) -> isize {
+    syscall(SYSCALL_COREDUMP, [buf.as_mut_ptr() as usize, buf.len(), 0, 0, 0, 0, 0])
+}
@@ -0,0 +1,69 @@
+use core::ptr::null_mut;
+
+use crate::error::{from_ret, SysResult};
+use crate::syscall::{sys_clock_gettime, sys_getitimer, sys_gettimeofday, sys_nanosleep, sys_setitimer};
+
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+pub const ITIMER_REAL: usize = 0;
+
+// the same layouts as kernel's
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug)]
+pub struct TimeSpec {
+    pub tv_sec: usize,
+    pub tv_nsec: usize,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug)]
+pub struct TimeVal {
+    pub tv_sec: usize,
+    pub tv_usec: usize,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug)]
+pub struct ITimerVal {
+    pub it_interval: TimeVal,
+    pub it_value: TimeVal,
+}
+
+// EINTR if a signal is caught before req passes, the time left is then in rem
+pub fn nanosleep(req: &TimeSpec, rem: Option<&mut TimeSpec>) -> SysResult {
+    from_ret(sys_nanosleep(req, rem.map_or(null_mut(), |rem| rem as *mut TimeSpec)))
+}
+
+// CLOCK_REALTIME is the time since the epoch, CLOCK_MONOTONIC since boot
+pub fn clock_gettime(clock_id: usize) -> SysResult<TimeSpec> {
+    let mut tp = TimeSpec::default();
+    from_ret(sys_clock_gettime(clock_id, &mut tp))?;
+    Ok(tp)
+}
+
+// the time since the epoch, with microseconds
+pub fn gettimeofday() -> SysResult<TimeVal> {
+    let mut tv = TimeVal::default();
+    from_ret(sys_gettimeofday(&mut tv))?;
+    Ok(tv)
+}
+
+pub fn getitimer(which: usize) -> SysResult<ITimerVal> {
+    let mut curr_value = ITimerVal::default();
+    from_ret(sys_getitimer(which, &mut curr_value))?;
+    Ok(curr_value)
+}
+
+// SIGALRM is sent when it_value passes, then every it_interval, return the old setting
+pub fn setitimer(which: usize, new_value: &ITimerVal) -> SysResult<ITimerVal> {
+    let mut old_value = ITimerVal::default();
+    from_ret(sys_setitimer(which, new_value, &mut old_value))?;
+    Ok(old_value)
+}
+
+// SIGALRM after seconds, 0 cancels it, return the seconds left of the old alarm
+pub fn alarm(seconds: usize) -> usize {
+    let new_value = ITimerVal { it_value: TimeVal { tv_sec: seconds, tv_usec: 0 }, ..ITimerVal::default() };
+    let old_value = setitimer(ITIMER_REAL, &new_value).unwrap();
+    old_value.it_value.tv_sec + (old_value.it_value.tv_usec > 0) as usize
+}
@@ -0,0 +1,49 @@
+use crate::error::{from_ret, SysResult};
+use crate::syscall::sys_ioctl;
+
+// local modes, the same bits as linux
+pub const ISIG: u32 = 0o1;
+pub const ICANON: u32 = 0o2;
+pub const ECHO: u32 = 0o10;
+
+const TCGETS: usize = 0x5401;
+const TCSETS: usize = 0x5402;
+const TIOCGPGRP: usize = 0x540f;
+const TIOCSPGRP: usize = 0x5410;
+
+pub const NCCS: usize = 19;
+
+// the layout of linux struct termios, kernel only acts on the local modes
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct Termios {
+    pub c_iflag: u32,
+    pub c_oflag: u32,
+    pub c_cflag: u32,
+    pub c_lflag: u32,
+    pub c_line: u8,
+    pub c_cc: [u8; NCCS],
+}
+
+pub fn tcgetattr(fd: usize) -> SysResult<Termios> {
+    let mut termios = Termios::default();
+    from_ret(sys_ioctl(fd, TCGETS, &mut termios as *mut Termios as usize))?;
+    Ok(termios)
+}
+
+pub fn tcsetattr(fd: usize, termios: &Termios) -> SysResult {
+    from_ret(sys_ioctl(fd, TCSETS, termios as *const Termios as usize))
+}
+
+// 0 if there is no foreground group
+pub fn tcgetpgrp(fd: usize) -> SysResult {
+    let mut pgid: i32 = 0;
+    from_ret(sys_ioctl(fd, TIOCGPGRP, &mut pgid as *mut i32 as usize))?;
+    Ok(pgid as usize)
+}
+
+// the foreground group gets the signals of Ctrl-C, Ctrl-Z and Ctrl-\
+pub fn tcsetpgrp(fd: usize, pgid: usize) -> SysResult {
+    let pgid = pgid as i32; // pid_t
+    from_ret(sys_ioctl(fd, TIOCSPGRP, &pgid as *const i32 as usize))
+}